@@ -3,15 +3,36 @@ use crate::Error;
 use casbin::{error::AdapterError, Result};
 use diesel::{
     self,
+    connection::TransactionManager,
     r2d2::{ConnectionManager, PooledConnection},
-    result::Error as DieselError,
-    sql_query, BoolExpressionMethods, Connection as DieselConnection, ExpressionMethods, QueryDsl,
-    RunQueryDsl,
+    result::{DatabaseErrorKind, Error as DieselError},
+    sql_query,
+    sql_types::{BigInt, Bool, Integer, Varchar},
+    BoolExpressionMethods, BoxableExpression, Connection as DieselConnection, ExpressionMethods,
+    IntoSql, OptionalExtension, QueryDsl, QueryResult, RunQueryDsl,
 };
 
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+#[cfg(feature = "postgres")]
+use crate::{adapter::NOTIFY_CHANNEL, changes::PolicyUpdate};
+
 use crate::{
-    adapter::TABLE_NAME,
-    models::{CasbinRule, NewCasbinRule},
+    adapter::{
+        matches_filter, rule_line, AUDIT_TABLE_NAME, CHANGE_TABLE_NAME, PRUNED_TABLE_NAME,
+        SNAPSHOT_RULE_TABLE_NAME, SNAPSHOT_TABLE_NAME, TABLE_NAME, VERSION_TABLE_NAME,
+    },
+    audit::{Audit, AuditQuery},
+    changes::{BatchRemoval, ChangeKind, ImportMode, RemovalMode, SaveSummary},
+    copy::{Checksum, CopyReport},
+    models::{
+        CasbinAuditLog, CasbinRule, CasbinRuleChange, CasbinSnapshot, NewCasbinRule,
+        NewCasbinRuleChange, NewCasbinSnapshot,
+    },
+    query::RuleQuery,
 };
 
 #[cfg(feature = "postgres")]
@@ -21,7 +42,41 @@ pub type Connection = diesel::MysqlConnection;
 #[cfg(feature = "sqlite")]
 pub type Connection = diesel::SqliteConnection;
 
-type Pool = PooledConnection<ConnectionManager<Connection>>;
+pub(crate) type Pool = PooledConnection<ConnectionManager<Connection>>;
+
+type Backend = <Connection as DieselConnection>::Backend;
+
+type RuleFilter = Box<dyn BoxableExpression<schema::casbin_rule::table, Backend, SqlType = Bool>>;
+
+// The most bind parameters a single statement may carry.
+#[cfg(feature = "postgres")]
+const MAX_BIND_PARAMS: usize = 65_535;
+#[cfg(feature = "mysql")]
+const MAX_BIND_PARAMS: usize = 65_535;
+// SQLITE_MAX_VARIABLE_NUMBER defaults to 999 before SQLite 3.32.
+#[cfg(feature = "sqlite")]
+const MAX_BIND_PARAMS: usize = 999;
+
+// `ptype` and `v0` to `v5`.
+const RULE_BIND_PARAMS: usize = 7;
+// `op`, `ptype`, `v0` to `v5` and `created_at`.
+const CHANGE_BIND_PARAMS: usize = 9;
+
+const INSERT_CHUNK_SIZE: usize = MAX_BIND_PARAMS / RULE_BIND_PARAMS;
+const CHANGE_CHUNK_SIZE: usize = MAX_BIND_PARAMS / CHANGE_BIND_PARAMS;
+
+// Keeps `IN (...)` lists well under every backend's bind parameter limit.
+const DELETE_CHUNK_SIZE: usize = 500;
+
+// What a write records besides the rules and the change log.
+#[derive(Default)]
+pub(crate) struct WriteContext {
+    pub audit: Option<Audit>,
+    // Receives the changes the write made once it has committed.
+    pub changes: Option<Arc<Mutex<Vec<CasbinRuleChange>>>>,
+    // Rolls the write back once it has run, after its changes are collected.
+    pub dry_run: bool,
+}
 
 #[cfg(feature = "postgres")]
 pub fn new(conn: Result<Pool>) -> Result<usize> {
@@ -42,7 +97,132 @@ pub fn new(conn: Result<Pool>) -> Result<usize> {
             "#
         ))
         .execute(&mut conn)
-        .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {CHANGE_TABLE_NAME} (
+                    id BIGSERIAL PRIMARY KEY,
+                    op VARCHAR NOT NULL,
+                    ptype VARCHAR NOT NULL,
+                    v0 VARCHAR NOT NULL,
+                    v1 VARCHAR NOT NULL,
+                    v2 VARCHAR NOT NULL,
+                    v3 VARCHAR NOT NULL,
+                    v4 VARCHAR NOT NULL,
+                    v5 VARCHAR NOT NULL,
+                    created_at BIGINT NOT NULL
+                );
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {VERSION_TABLE_NAME} (
+                    id INTEGER PRIMARY KEY,
+                    version BIGINT NOT NULL
+                );
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {PRUNED_TABLE_NAME} (
+                    id INTEGER PRIMARY KEY,
+                    pruned_through BIGINT NOT NULL
+                );
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {AUDIT_TABLE_NAME} (
+                    id BIGSERIAL PRIMARY KEY,
+                    operation VARCHAR NOT NULL,
+                    op VARCHAR NOT NULL,
+                    ptype VARCHAR NOT NULL,
+                    v0 VARCHAR NOT NULL,
+                    v1 VARCHAR NOT NULL,
+                    v2 VARCHAR NOT NULL,
+                    v3 VARCHAR NOT NULL,
+                    v4 VARCHAR NOT NULL,
+                    v5 VARCHAR NOT NULL,
+                    actor VARCHAR NOT NULL,
+                    created_at BIGINT NOT NULL
+                );
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {SNAPSHOT_TABLE_NAME} (
+                    id SERIAL PRIMARY KEY,
+                    name VARCHAR NOT NULL,
+                    version BIGINT NOT NULL,
+                    rule_count BIGINT NOT NULL,
+                    created_at BIGINT NOT NULL,
+                    CONSTRAINT unique_snapshot_name UNIQUE(name)
+                );
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {SNAPSHOT_RULE_TABLE_NAME} (
+                    id SERIAL PRIMARY KEY,
+                    snapshot_id INTEGER NOT NULL,
+                    ptype VARCHAR NOT NULL,
+                    v0 VARCHAR NOT NULL,
+                    v1 VARCHAR NOT NULL,
+                    v2 VARCHAR NOT NULL,
+                    v3 VARCHAR NOT NULL,
+                    v4 VARCHAR NOT NULL,
+                    v5 VARCHAR NOT NULL
+                );
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE INDEX IF NOT EXISTS idx_snapshot_rule_snapshot_id
+                ON {SNAPSHOT_RULE_TABLE_NAME} (snapshot_id);
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                INSERT INTO {VERSION_TABLE_NAME} (id, version)
+                SELECT 1, COALESCE(MAX(id), 0) FROM {CHANGE_TABLE_NAME}
+                ON CONFLICT (id) DO NOTHING;
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                INSERT INTO {PRUNED_TABLE_NAME} (id, pruned_through) VALUES (1, 0)
+                ON CONFLICT (id) DO NOTHING;
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .map_err(Error::DieselError)
+        .and_then(|n| check_schema(&mut conn).map(|()| n))
+        .map_err(|err| AdapterError(Box::new(err)).into())
     })
 }
 
@@ -66,13 +246,158 @@ pub fn new(conn: Result<Pool>) -> Result<usize> {
             "#
         ))
         .execute(&mut conn)
-        .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {CHANGE_TABLE_NAME} (
+                    id BIGINT NOT NULL AUTO_INCREMENT,
+                    op VARCHAR(6) NOT NULL,
+                    ptype VARCHAR(12) NOT NULL,
+                    v0 VARCHAR(128) NOT NULL,
+                    v1 VARCHAR(128) NOT NULL,
+                    v2 VARCHAR(128) NOT NULL,
+                    v3 VARCHAR(128) NOT NULL,
+                    v4 VARCHAR(128) NOT NULL,
+                    v5 VARCHAR(128) NOT NULL,
+                    created_at BIGINT NOT NULL,
+                    PRIMARY KEY(id)
+                ) ENGINE=InnoDB DEFAULT CHARSET=utf8;
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {VERSION_TABLE_NAME} (
+                    id INTEGER PRIMARY KEY,
+                    version BIGINT NOT NULL
+                ) ENGINE=InnoDB DEFAULT CHARSET=utf8;
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {PRUNED_TABLE_NAME} (
+                    id INTEGER PRIMARY KEY,
+                    pruned_through BIGINT NOT NULL
+                ) ENGINE=InnoDB DEFAULT CHARSET=utf8;
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {AUDIT_TABLE_NAME} (
+                    id BIGINT NOT NULL AUTO_INCREMENT,
+                    operation VARCHAR(32) NOT NULL,
+                    op VARCHAR(6) NOT NULL,
+                    ptype VARCHAR(12) NOT NULL,
+                    v0 VARCHAR(128) NOT NULL,
+                    v1 VARCHAR(128) NOT NULL,
+                    v2 VARCHAR(128) NOT NULL,
+                    v3 VARCHAR(128) NOT NULL,
+                    v4 VARCHAR(128) NOT NULL,
+                    v5 VARCHAR(128) NOT NULL,
+                    actor VARCHAR(255) NOT NULL,
+                    created_at BIGINT NOT NULL,
+                    PRIMARY KEY(id)
+                ) ENGINE=InnoDB DEFAULT CHARSET=utf8;
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {SNAPSHOT_TABLE_NAME} (
+                    id INT NOT NULL AUTO_INCREMENT,
+                    name VARCHAR(128) NOT NULL,
+                    version BIGINT NOT NULL,
+                    rule_count BIGINT NOT NULL,
+                    created_at BIGINT NOT NULL,
+                    PRIMARY KEY(id),
+                    CONSTRAINT unique_snapshot_name UNIQUE(name)
+                ) ENGINE=InnoDB DEFAULT CHARSET=utf8;
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {SNAPSHOT_RULE_TABLE_NAME} (
+                    id INT NOT NULL AUTO_INCREMENT,
+                    snapshot_id INT NOT NULL,
+                    ptype VARCHAR(12) NOT NULL,
+                    v0 VARCHAR(128) NOT NULL,
+                    v1 VARCHAR(128) NOT NULL,
+                    v2 VARCHAR(128) NOT NULL,
+                    v3 VARCHAR(128) NOT NULL,
+                    v4 VARCHAR(128) NOT NULL,
+                    v5 VARCHAR(128) NOT NULL,
+                    PRIMARY KEY(id),
+                    INDEX idx_snapshot_rule_snapshot_id (snapshot_id)
+                ) ENGINE=InnoDB DEFAULT CHARSET=utf8;
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                INSERT IGNORE INTO {VERSION_TABLE_NAME} (id, version)
+                SELECT 1, COALESCE(MAX(id), 0) FROM {CHANGE_TABLE_NAME};
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                INSERT IGNORE INTO {PRUNED_TABLE_NAME} (id, pruned_through) VALUES (1, 0);
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .map_err(Error::DieselError)
+        .and_then(|n| check_schema(&mut conn).map(|()| n))
+        .map_err(|err| AdapterError(Box::new(err)).into())
     })
 }
 
+// Waits this long for a lock another connection holds before failing with
+// "database is locked".
+#[cfg(feature = "sqlite")]
+const BUSY_TIMEOUT_MS: u32 = 10_000;
+
+// Sets the busy timeout on every connection `DieselAdapter::new` opens.
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+pub(crate) struct BusyTimeout;
+
+#[cfg(feature = "sqlite")]
+impl diesel::r2d2::CustomizeConnection<Connection, diesel::r2d2::Error> for BusyTimeout {
+    fn on_acquire(&self, conn: &mut Connection) -> std::result::Result<(), diesel::r2d2::Error> {
+        sql_query(format!("PRAGMA busy_timeout = {BUSY_TIMEOUT_MS}"))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
 #[cfg(feature = "sqlite")]
 pub fn new(conn: Result<Pool>) -> Result<usize> {
     conn.and_then(|mut conn| {
+        // WAL mode lasts across connections, and lets writers commit while a
+        // load's read transaction is still open.
+        sql_query("PRAGMA journal_mode = WAL")
+            .execute(&mut conn)
+            .map_err(|err| AdapterError(Box::new(Error::DieselError(err))))?;
+
         sql_query(format!(
             r#"
                 CREATE TABLE IF NOT EXISTS {TABLE_NAME} (
@@ -89,36 +414,232 @@ pub fn new(conn: Result<Pool>) -> Result<usize> {
             "#
         ))
         .execute(&mut conn)
-        .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {CHANGE_TABLE_NAME} (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    op VARCHAR(6) NOT NULL,
+                    ptype VARCHAR(12) NOT NULL,
+                    v0 VARCHAR(128) NOT NULL,
+                    v1 VARCHAR(128) NOT NULL,
+                    v2 VARCHAR(128) NOT NULL,
+                    v3 VARCHAR(128) NOT NULL,
+                    v4 VARCHAR(128) NOT NULL,
+                    v5 VARCHAR(128) NOT NULL,
+                    created_at BIGINT NOT NULL
+                );
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {VERSION_TABLE_NAME} (
+                    id INTEGER PRIMARY KEY,
+                    version BIGINT NOT NULL
+                );
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {PRUNED_TABLE_NAME} (
+                    id INTEGER PRIMARY KEY,
+                    pruned_through BIGINT NOT NULL
+                );
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {AUDIT_TABLE_NAME} (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    operation VARCHAR(32) NOT NULL,
+                    op VARCHAR(6) NOT NULL,
+                    ptype VARCHAR(12) NOT NULL,
+                    v0 VARCHAR(128) NOT NULL,
+                    v1 VARCHAR(128) NOT NULL,
+                    v2 VARCHAR(128) NOT NULL,
+                    v3 VARCHAR(128) NOT NULL,
+                    v4 VARCHAR(128) NOT NULL,
+                    v5 VARCHAR(128) NOT NULL,
+                    actor VARCHAR(255) NOT NULL,
+                    created_at BIGINT NOT NULL
+                );
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {SNAPSHOT_TABLE_NAME} (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name VARCHAR(128) NOT NULL,
+                    version BIGINT NOT NULL,
+                    rule_count BIGINT NOT NULL,
+                    created_at BIGINT NOT NULL,
+                    CONSTRAINT unique_snapshot_name UNIQUE(name)
+                );
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {SNAPSHOT_RULE_TABLE_NAME} (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    snapshot_id INTEGER NOT NULL,
+                    ptype VARCHAR(12) NOT NULL,
+                    v0 VARCHAR(128) NOT NULL,
+                    v1 VARCHAR(128) NOT NULL,
+                    v2 VARCHAR(128) NOT NULL,
+                    v3 VARCHAR(128) NOT NULL,
+                    v4 VARCHAR(128) NOT NULL,
+                    v5 VARCHAR(128) NOT NULL
+                );
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                CREATE INDEX IF NOT EXISTS idx_snapshot_rule_snapshot_id
+                ON {SNAPSHOT_RULE_TABLE_NAME} (snapshot_id);
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                INSERT OR IGNORE INTO {VERSION_TABLE_NAME} (id, version)
+                SELECT 1, COALESCE(MAX(id), 0) FROM {CHANGE_TABLE_NAME};
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .and_then(|_| {
+            sql_query(format!(
+                r#"
+                INSERT OR IGNORE INTO {PRUNED_TABLE_NAME} (id, pruned_through) VALUES (1, 0);
+            "#
+            ))
+            .execute(&mut conn)
+        })
+        .map_err(Error::DieselError)
+        .and_then(|n| check_schema(&mut conn).map(|()| n))
+        .map_err(|err| AdapterError(Box::new(err)).into())
     })
 }
 
-pub fn remove_policy(mut conn: Pool, pt: &str, rule: Vec<String>) -> Result<bool> {
+// Loads no rows from every table, which fails if a table that already existed
+// lacks a column this crate expects.
+fn check_schema(conn: &mut Connection) -> std::result::Result<(), Error> {
+    use schema::*;
+
+    fn mismatch(table: &str) -> impl FnOnce(DieselError) -> Error + '_ {
+        move |error| Error::SchemaMismatch {
+            table: table.to_owned(),
+            error,
+        }
+    }
+
+    casbin_rule::table
+        .limit(0)
+        .load::<CasbinRule>(conn)
+        .map_err(mismatch(TABLE_NAME))?;
+    casbin_rule_change::table
+        .limit(0)
+        .load::<CasbinRuleChange>(conn)
+        .map_err(mismatch(CHANGE_TABLE_NAME))?;
+    casbin_policy_version::table
+        .limit(0)
+        .load::<(i32, i64)>(conn)
+        .map_err(mismatch(VERSION_TABLE_NAME))?;
+    casbin_rule_change_pruned::table
+        .limit(0)
+        .load::<(i32, i64)>(conn)
+        .map_err(mismatch(PRUNED_TABLE_NAME))?;
+    casbin_audit_log::table
+        .limit(0)
+        .load::<CasbinAuditLog>(conn)
+        .map_err(mismatch(AUDIT_TABLE_NAME))?;
+    casbin_snapshot::table
+        .limit(0)
+        .load::<CasbinSnapshot>(conn)
+        .map_err(mismatch(SNAPSHOT_TABLE_NAME))?;
+    casbin_snapshot_rule::table
+        .limit(0)
+        .load::<(
+            i32,
+            i32,
+            String,
+            String,
+            String,
+            String,
+            String,
+            String,
+            String,
+        )>(conn)
+        .map_err(mismatch(SNAPSHOT_RULE_TABLE_NAME))?;
+
+    Ok(())
+}
+
+pub fn remove_policy(
+    mut conn: Pool,
+    pt: &str,
+    rule: Vec<String>,
+    ctx: WriteContext,
+) -> Result<bool> {
     use schema::casbin_rule::dsl::*;
 
-    let rule = normalize_casbin_rule(rule, 0);
+    let rule = normalize_casbin_rule(rule);
 
-    let filter = ptype
-        .eq(pt)
-        .and(v0.eq(&rule[0]))
-        .and(v1.eq(&rule[1]))
-        .and(v2.eq(&rule[2]))
-        .and(v3.eq(&rule[3]))
-        .and(v4.eq(&rule[4]))
-        .and(v5.eq(&rule[5]));
+    write_transaction::<_, DieselError, _>(&mut conn, &ctx, |conn, _| {
+        let filter = ptype
+            .eq(pt)
+            .and(v0.eq(&rule[0]))
+            .and(v1.eq(&rule[1]))
+            .and(v2.eq(&rule[2]))
+            .and(v3.eq(&rule[3]))
+            .and(v4.eq(&rule[4]))
+            .and(v5.eq(&rule[5]));
 
-    diesel::delete(casbin_rule.filter(filter))
-        .execute(&mut conn)
-        .map(|n| n == 1)
-        .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+        let n = diesel::delete(casbin_rule.filter(filter)).execute(conn)?;
+        if n == 1 {
+            record_changes(conn, ChangeKind::Remove, &[new_casbin_rule(pt, &rule)])?;
+        }
+
+        Ok(n == 1)
+    })
+    .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
 }
 
-pub fn remove_policies(mut conn: Pool, pt: &str, rules: Vec<Vec<String>>) -> Result<bool> {
+pub fn remove_policies(
+    mut conn: Pool,
+    pt: &str,
+    rules: Vec<Vec<String>>,
+    mode: RemovalMode,
+    ctx: WriteContext,
+) -> Result<BatchRemoval> {
     use schema::casbin_rule::dsl::*;
 
-    conn.transaction::<_, DieselError, _>(|conn| {
-        for rule in rules {
-            let rule = normalize_casbin_rule(rule, 0);
+    write_transaction::<_, Error, _>(&mut conn, &ctx, |conn, _| {
+        let mut removed = Vec::with_capacity(rules.len());
+        let mut report = BatchRemoval::default();
+
+        for original in rules {
+            let rule = normalize_casbin_rule(original.clone());
 
             let filter = ptype
                 .eq(pt)
@@ -129,15 +650,26 @@ pub fn remove_policies(mut conn: Pool, pt: &str, rules: Vec<Vec<String>>) -> Res
                 .and(v4.eq(&rule[4]))
                 .and(v5.eq(&rule[5]));
 
-            match diesel::delete(casbin_rule.filter(filter)).execute(conn) {
-                Ok(1) => continue,
-                _ => return Err(DieselError::RollbackTransaction),
+            match diesel::delete(casbin_rule.filter(filter)).execute(conn)? {
+                1 => {
+                    removed.push(new_casbin_rule(pt, &rule));
+                    report.removed.push(original);
+                }
+                _ if mode == RemovalMode::BestEffort => report.missing.push(original),
+                _ => {
+                    return Err(Error::RuleNotFound {
+                        ptype: pt.to_owned(),
+                        rule: original,
+                    })
+                }
             }
         }
 
-        Ok(true)
+        record_changes(conn, ChangeKind::Remove, &removed)?;
+
+        Ok(report)
     })
-    .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+    .map_err(|err| AdapterError(Box::new(err)).into())
 }
 
 pub fn remove_filtered_policy(
@@ -145,147 +677,1389 @@ pub fn remove_filtered_policy(
     pt: &str,
     field_index: usize,
     field_values: Vec<String>,
-) -> Result<bool> {
+    ctx: WriteContext,
+) -> Result<Vec<CasbinRule>> {
+    write_transaction::<_, DieselError, _>(&mut conn, &ctx, |conn, _| {
+        let removed = delete_filtered(conn, pt, field_index, &field_values)?;
+        record_changes(conn, ChangeKind::Remove, &to_new_casbin_rules(&removed))?;
+
+        Ok(removed)
+    })
+    .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+}
+
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+fn delete_filtered(
+    conn: &mut Connection,
+    pt: &str,
+    field_index: usize,
+    field_values: &[String],
+) -> QueryResult<Vec<CasbinRule>> {
     use schema::casbin_rule::dsl::*;
 
-    let field_values = normalize_casbin_rule(field_values, field_index);
-
-    let boxed_query = if field_index == 5 {
-        diesel::delete(casbin_rule)
-            .filter(ptype.eq(pt).and(v5.is_null().or(v5.eq(&field_values[0]))))
-            .into_boxed()
-    } else if field_index == 4 {
-        diesel::delete(casbin_rule)
-            .filter(
-                ptype
-                    .eq(pt)
-                    .and(v4.is_null().or(v4.eq(&field_values[0])))
-                    .and(v5.is_null().or(v5.eq(&field_values[1]))),
-            )
-            .into_boxed()
-    } else if field_index == 3 {
-        diesel::delete(casbin_rule)
-            .filter(
-                ptype
-                    .eq(pt)
-                    .and(v3.is_null().or(v3.eq(&field_values[0])))
-                    .and(v4.is_null().or(v4.eq(&field_values[1])))
-                    .and(v5.is_null().or(v5.eq(&field_values[2]))),
-            )
-            .into_boxed()
-    } else if field_index == 2 {
-        diesel::delete(casbin_rule)
-            .filter(
-                ptype
-                    .eq(pt)
-                    .and(v2.is_null().or(v2.eq(&field_values[0])))
-                    .and(v3.is_null().or(v3.eq(&field_values[1])))
-                    .and(v4.is_null().or(v4.eq(&field_values[2])))
-                    .and(v5.is_null().or(v5.eq(&field_values[3]))),
-            )
-            .into_boxed()
-    } else if field_index == 1 {
-        diesel::delete(casbin_rule)
-            .filter(
-                ptype
-                    .eq(pt)
-                    .and(v1.is_null().or(v1.eq(&field_values[0])))
-                    .and(v2.is_null().or(v2.eq(&field_values[1])))
-                    .and(v3.is_null().or(v3.eq(&field_values[2])))
-                    .and(v4.is_null().or(v4.eq(&field_values[3])))
-                    .and(v5.is_null().or(v5.eq(&field_values[4]))),
-            )
-            .into_boxed()
-    } else {
-        diesel::delete(casbin_rule)
-            .filter(
-                ptype
-                    .eq(pt)
-                    .and(v0.is_null().or(v0.eq(&field_values[0])))
-                    .and(v1.is_null().or(v1.eq(&field_values[1])))
-                    .and(v2.is_null().or(v2.eq(&field_values[2])))
-                    .and(v3.is_null().or(v3.eq(&field_values[3])))
-                    .and(v4.is_null().or(v4.eq(&field_values[4])))
-                    .and(v5.is_null().or(v5.eq(&field_values[5]))),
-            )
-            .into_boxed()
-    };
+    diesel::delete(casbin_rule.filter(filtered_rule(pt, field_index, field_values)))
+        .returning((id, ptype, v0, v1, v2, v3, v4, v5))
+        .get_results::<CasbinRule>(conn)
+}
 
-    boxed_query
-        .execute(&mut conn)
-        .map(|n| n >= 1)
-        .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+// MySQL has no `DELETE ... RETURNING`, so lock the matching rows, then delete
+// exactly those.
+#[cfg(feature = "mysql")]
+fn delete_filtered(
+    conn: &mut Connection,
+    pt: &str,
+    field_index: usize,
+    field_values: &[String],
+) -> QueryResult<Vec<CasbinRule>> {
+    use schema::casbin_rule::dsl::*;
+
+    let removed = casbin_rule
+        .filter(filtered_rule(pt, field_index, field_values))
+        .for_update()
+        .load::<CasbinRule>(conn)?;
+
+    for ids in removed.chunks(DELETE_CHUNK_SIZE) {
+        diesel::delete(casbin_rule.filter(id.eq_any(ids.iter().map(|r| r.id)))).execute(conn)?;
+    }
+
+    Ok(removed)
 }
 
-pub(crate) fn clear_policy(mut conn: Pool) -> Result<()> {
-    use schema::casbin_rule::dsl::casbin_rule;
-    diesel::delete(casbin_rule)
-        .execute(&mut conn)
-        .map(|_| ())
-        .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+// The rows `query` matches. A value is compared with the stored columns, so
+// an empty one also matches the columns a shorter rule leaves empty.
+fn queried_rule(query: &RuleQuery) -> RuleFilter {
+    use schema::casbin_rule::dsl::*;
+
+    let mut filter: RuleFilter = Box::new(true.into_sql::<Bool>());
+
+    if let Some(ref pt) = query.ptype {
+        filter = Box::new(filter.and(ptype.eq(pt.clone())));
+    }
+    if let Some(ref value) = query.value {
+        let value = value.clone();
+        filter = match query.field_index {
+            Some(0) => Box::new(filter.and(v0.eq(value))),
+            Some(1) => Box::new(filter.and(v1.eq(value))),
+            Some(2) => Box::new(filter.and(v2.eq(value))),
+            Some(3) => Box::new(filter.and(v3.eq(value))),
+            Some(4) => Box::new(filter.and(v4.eq(value))),
+            Some(5) => Box::new(filter.and(v5.eq(value))),
+            // no stored rule has more than six values
+            Some(_) => Box::new(filter.and(false.into_sql::<Bool>())),
+            None => Box::new(
+                filter.and(
+                    v0.eq(value.clone())
+                        .or(v1.eq(value.clone()))
+                        .or(v2.eq(value.clone()))
+                        .or(v3.eq(value.clone()))
+                        .or(v4.eq(value.clone()))
+                        .or(v5.eq(value)),
+                ),
+            ),
+        };
+    }
+
+    filter
 }
 
-pub(crate) fn save_policy(mut conn: Pool, rules: Vec<NewCasbinRule>) -> Result<()> {
-    use schema::casbin_rule::dsl::casbin_rule;
+// Builds the `WHERE` clause for a filtered operation the way casbin matches
+// filters: `field_values[i]` applies to column `field_index + i`, and an empty
+// value matches anything, so it adds no condition at all.
+fn filtered_rule(pt: &str, field_index: usize, field_values: &[String]) -> RuleFilter {
+    use schema::casbin_rule::dsl::*;
 
-    conn.transaction::<_, DieselError, _>(|conn| {
-        if diesel::delete(casbin_rule).execute(conn).is_err() {
-            return Err(DieselError::RollbackTransaction);
+    let mut filter: RuleFilter = Box::new(ptype.eq(pt.to_owned()));
+
+    for (i, value) in field_values.iter().enumerate() {
+        if value.is_empty() {
+            continue;
         }
 
-        diesel::insert_into(casbin_rule)
-            .values(&rules)
-            .execute(conn)
-            .and_then(|n| {
-                if n == rules.len() {
-                    Ok(())
-                } else {
-                    Err(DieselError::RollbackTransaction)
-                }
-            })
-            .map_err(|_| DieselError::RollbackTransaction)
+        let value = value.to_owned();
+        filter = match field_index + i {
+            0 => Box::new(filter.and(v0.eq(value))),
+            1 => Box::new(filter.and(v1.eq(value))),
+            2 => Box::new(filter.and(v2.eq(value))),
+            3 => Box::new(filter.and(v3.eq(value))),
+            4 => Box::new(filter.and(v4.eq(value))),
+            5 => Box::new(filter.and(v5.eq(value))),
+            // no stored rule has more than six values
+            _ => Box::new(filter.and(false.into_sql::<Bool>())),
+        };
+    }
+
+    filter
+}
+
+pub(crate) fn clear_policy(mut conn: Pool, ctx: WriteContext) -> Result<()> {
+    use schema::casbin_rule::dsl::casbin_rule;
+
+    write_transaction::<_, DieselError, _>(&mut conn, &ctx, |conn, _| {
+        record_table(conn, ChangeKind::Remove)?;
+        diesel::delete(casbin_rule).execute(conn).map(|_| ())
     })
     .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
 }
 
-pub(crate) fn load_policy(mut conn: Pool) -> Result<Vec<CasbinRule>> {
+pub(crate) fn save_policy(
+    mut conn: Pool,
+    rules: Vec<NewCasbinRule>,
+    expected_version: Option<i64>,
+    ctx: WriteContext,
+) -> Result<i64> {
     use schema::casbin_rule::dsl::casbin_rule;
 
-    casbin_rule
-        .load::<CasbinRule>(&mut conn)
-        .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+    write_transaction::<_, Error, _>(&mut conn, &ctx, |conn, version| {
+        if let Some(expected) = expected_version {
+            if expected != version {
+                return Err(Error::VersionConflict {
+                    expected,
+                    actual: version,
+                });
+            }
+        }
+
+        // Only the rules that differ are written, so saving an unchanged
+        // policy adds nothing to the change or audit log.
+        let existing = casbin_rule.load::<CasbinRule>(conn)?;
+        apply_diff(conn, existing, rules)?;
+
+        current_version(conn).map_err(Error::from)
+    })
+    .map_err(|err| AdapterError(Box::new(err)).into())
 }
 
-pub(crate) fn add_policy(mut conn: Pool, new_rule: NewCasbinRule) -> Result<bool> {
+pub(crate) fn save_policy_diff(
+    mut conn: Pool,
+    rules: Vec<NewCasbinRule>,
+    ctx: WriteContext,
+) -> Result<SaveSummary> {
     use schema::casbin_rule::dsl::casbin_rule;
 
-    diesel::insert_into(casbin_rule)
-        .values(&new_rule)
-        .execute(&mut conn)
-        .map(|n| n == 1)
-        .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+    write_transaction::<_, DieselError, _>(&mut conn, &ctx, |conn, _| {
+        let existing = casbin_rule.load::<CasbinRule>(conn)?;
+        apply_diff(conn, existing, rules)
+    })
+    .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+}
+
+pub(crate) fn policy_at(mut conn: Pool, timestamp: i64) -> Result<Vec<NewCasbinRule>> {
+    // One snapshot covers both the table and the change log, without keeping
+    // writers out.
+    in_read_snapshot(&mut conn, |conn| rules_at(conn, timestamp))
+        .map_err(|err| AdapterError(Box::new(err)).into())
 }
 
-pub(crate) fn add_policies(mut conn: Pool, new_rules: Vec<NewCasbinRule>) -> Result<bool> {
+pub(crate) fn restore_policy_at(
+    mut conn: Pool,
+    timestamp: i64,
+    ctx: WriteContext,
+) -> Result<SaveSummary> {
     use schema::casbin_rule::dsl::casbin_rule;
 
-    conn.transaction::<_, DieselError, _>(|conn| {
-        diesel::insert_into(casbin_rule)
-            .values(&new_rules)
-            .execute(&mut *conn)
-            .and_then(|n| {
-                if n == new_rules.len() {
-                    Ok(true)
-                } else {
-                    Err(DieselError::RollbackTransaction)
-                }
+    write_transaction::<_, Error, _>(&mut conn, &ctx, |conn, _| {
+        let rules = rules_at(conn, timestamp)?;
+        let existing = casbin_rule.load::<CasbinRule>(conn)?;
+        apply_diff(conn, existing, rules).map_err(Error::from)
+    })
+    .map_err(|err| AdapterError(Box::new(err)).into())
+}
+
+// Rebuilds the rules as they stood at `timestamp` by undoing, newest first,
+// every change recorded after it. Changes are applied in `id` order, so the
+// timestamp is first resolved to the last change made by then; a change with
+// a later id is undone even if its timestamp is earlier. Once changes have
+// been pruned, a timestamp older than every change left cannot be resolved.
+fn rules_at(
+    conn: &mut Connection,
+    timestamp: i64,
+) -> std::result::Result<Vec<NewCasbinRule>, Error> {
+    use schema::{casbin_rule, casbin_rule_change};
+
+    let mut rules = casbin_rule::table
+        .load::<CasbinRule>(conn)?
+        .into_iter()
+        .map(NewCasbinRule::from)
+        .collect::<HashSet<_>>();
+
+    let cutoff = casbin_rule_change::table
+        .filter(casbin_rule_change::created_at.le(timestamp))
+        .select(diesel::dsl::max(casbin_rule_change::id))
+        .first::<Option<i64>>(conn)?;
+    let cutoff = match (cutoff, pruned_through(conn)?) {
+        (Some(cutoff), _) => cutoff,
+        (None, 0) => 0,
+        (None, pruned) => return Err(Error::ChangesPruned { pruned }),
+    };
+
+    let changes = casbin_rule_change::table
+        .filter(casbin_rule_change::id.gt(cutoff))
+        .order(casbin_rule_change::id.desc())
+        .load::<CasbinRuleChange>(conn)?;
+
+    for change in changes {
+        let kind = ChangeKind::from_str(&change.op);
+        let rule = NewCasbinRule::from(change);
+
+        match kind {
+            Some(ChangeKind::Add) => {
+                rules.remove(&rule);
+            }
+            Some(ChangeKind::Remove) => {
+                rules.insert(rule);
+            }
+            None => {}
+        }
+    }
+
+    let mut rules = rules.into_iter().collect::<Vec<_>>();
+    rules.sort_by(|a, b| rule_key(a).cmp(&rule_key(b)));
+
+    Ok(rules)
+}
+
+fn rule_key(rule: &NewCasbinRule) -> [&str; 7] {
+    [
+        &rule.ptype,
+        &rule.v0,
+        &rule.v1,
+        &rule.v2,
+        &rule.v3,
+        &rule.v4,
+        &rule.v5,
+    ]
+}
+
+pub(crate) fn create_snapshot(mut conn: Pool, snapshot_name: String) -> Result<CasbinSnapshot> {
+    use schema::{casbin_rule, casbin_snapshot, casbin_snapshot_rule};
+
+    // The version lock keeps writers out while the rules are copied.
+    conn.transaction::<_, Error, _>(|conn| {
+        let policy_version = lock_version(conn)?;
+
+        if find_snapshot(conn, &snapshot_name)?.is_some() {
+            return Err(Error::SnapshotExists(snapshot_name.clone()));
+        }
+
+        let rule_count = casbin_rule::table.count().get_result::<i64>(conn)?;
+        diesel::insert_into(casbin_snapshot::table)
+            .values(NewCasbinSnapshot {
+                name: &snapshot_name,
+                version: policy_version,
+                rule_count,
+                created_at: now_millis(conn)?,
             })
-            .map_err(|_| DieselError::RollbackTransaction)
+            .execute(conn)?;
+
+        let snapshot = find_snapshot(conn, &snapshot_name)?.ok_or(DieselError::NotFound)?;
+
+        let rows = casbin_rule::table.order(casbin_rule::id.asc()).select((
+            snapshot.id.into_sql::<Integer>(),
+            casbin_rule::ptype,
+            casbin_rule::v0,
+            casbin_rule::v1,
+            casbin_rule::v2,
+            casbin_rule::v3,
+            casbin_rule::v4,
+            casbin_rule::v5,
+        ));
+
+        diesel::insert_into(casbin_snapshot_rule::table)
+            .values(rows)
+            .into_columns((
+                casbin_snapshot_rule::snapshot_id,
+                casbin_snapshot_rule::ptype,
+                casbin_snapshot_rule::v0,
+                casbin_snapshot_rule::v1,
+                casbin_snapshot_rule::v2,
+                casbin_snapshot_rule::v3,
+                casbin_snapshot_rule::v4,
+                casbin_snapshot_rule::v5,
+            ))
+            .execute(conn)?;
+
+        Ok(snapshot)
     })
-    .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+    .map_err(|err| AdapterError(Box::new(err)).into())
+}
+
+pub(crate) fn list_snapshots(mut conn: Pool) -> Result<Vec<CasbinSnapshot>> {
+    use schema::casbin_snapshot::dsl::*;
+
+    casbin_snapshot
+        .order(id.asc())
+        .load::<CasbinSnapshot>(&mut conn)
+        .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+}
+
+// Returns the rules only in `from` and the rules only in `to`.
+pub(crate) fn diff_snapshots(
+    mut conn: Pool,
+    from: String,
+    to: String,
+) -> Result<(Vec<NewCasbinRule>, Vec<NewCasbinRule>)> {
+    conn.transaction::<_, Error, _>(|conn| {
+        let from = snapshot_rules(conn, &from)?;
+        let to = snapshot_rules(conn, &to)?;
+
+        let mut removed = from.difference(&to).cloned().collect::<Vec<_>>();
+        let mut added = to.difference(&from).cloned().collect::<Vec<_>>();
+        removed.sort_by(|a, b| rule_key(a).cmp(&rule_key(b)));
+        added.sort_by(|a, b| rule_key(a).cmp(&rule_key(b)));
+
+        Ok((removed, added))
+    })
+    .map_err(|err| AdapterError(Box::new(err)).into())
+}
+
+pub(crate) fn restore_snapshot(
+    mut conn: Pool,
+    snapshot_name: String,
+    ctx: WriteContext,
+) -> Result<SaveSummary> {
+    use schema::casbin_rule::dsl::casbin_rule;
+
+    write_transaction::<_, Error, _>(&mut conn, &ctx, |conn, _| {
+        let rules = snapshot_rules(conn, &snapshot_name)?;
+        let existing = casbin_rule.load::<CasbinRule>(conn)?;
+        apply_diff(conn, existing, rules.into_iter().collect()).map_err(Error::from)
+    })
+    .map_err(|err| AdapterError(Box::new(err)).into())
+}
+
+pub(crate) fn delete_snapshot(mut conn: Pool, snapshot_name: String) -> Result<bool> {
+    use schema::{casbin_snapshot, casbin_snapshot_rule};
+
+    conn.transaction::<_, DieselError, _>(|conn| {
+        let snapshot = match find_snapshot(conn, &snapshot_name)? {
+            Some(snapshot) => snapshot,
+            None => return Ok(false),
+        };
+
+        diesel::delete(
+            casbin_snapshot_rule::table.filter(casbin_snapshot_rule::snapshot_id.eq(snapshot.id)),
+        )
+        .execute(conn)?;
+        diesel::delete(casbin_snapshot::table.find(snapshot.id)).execute(conn)?;
+
+        Ok(true)
+    })
+    .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+}
+
+fn find_snapshot(
+    conn: &mut Connection,
+    snapshot_name: &str,
+) -> QueryResult<Option<CasbinSnapshot>> {
+    use schema::casbin_snapshot::dsl::*;
+
+    casbin_snapshot
+        .filter(name.eq(snapshot_name))
+        .first::<CasbinSnapshot>(conn)
+        .optional()
+}
+
+fn snapshot_rules(
+    conn: &mut Connection,
+    snapshot_name: &str,
+) -> std::result::Result<HashSet<NewCasbinRule>, Error> {
+    use schema::casbin_snapshot_rule::dsl::*;
+
+    let snapshot = find_snapshot(conn, snapshot_name)?
+        .ok_or_else(|| Error::SnapshotNotFound(snapshot_name.to_owned()))?;
+
+    let rules = casbin_snapshot_rule
+        .filter(snapshot_id.eq(snapshot.id))
+        .select((ptype, v0, v1, v2, v3, v4, v5))
+        .load::<NewCasbinRule>(conn)?;
+
+    Ok(rules.into_iter().collect())
+}
+
+pub(crate) fn replace_filtered_policy(
+    mut conn: Pool,
+    pt: &str,
+    field_index: usize,
+    field_values: Vec<String>,
+    new_rules: Vec<NewCasbinRule>,
+    ctx: WriteContext,
+) -> Result<SaveSummary> {
+    use schema::casbin_rule::dsl::casbin_rule;
+
+    write_transaction::<_, DieselError, _>(&mut conn, &ctx, |conn, _| {
+        let existing = casbin_rule
+            .filter(filtered_rule(pt, field_index, &field_values))
+            .load::<CasbinRule>(conn)?;
+        apply_diff(conn, existing, new_rules)
+    })
+    .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+}
+
+// Turns `existing` into `rules`: rows missing from `rules` are deleted and
+// rules without a row are inserted, while the rest are left untouched.
+fn apply_diff(
+    conn: &mut Connection,
+    existing: Vec<CasbinRule>,
+    rules: Vec<NewCasbinRule>,
+) -> QueryResult<SaveSummary> {
+    use schema::casbin_rule::dsl::*;
+
+    let mut existing = existing
+        .into_iter()
+        .map(|rule| (rule.id, rule))
+        .map(|(rule_id, rule)| (NewCasbinRule::from(rule), rule_id))
+        .collect::<HashMap<_, _>>();
+
+    let mut seen = HashSet::with_capacity(rules.len());
+    let mut inserted = Vec::new();
+    for rule in rules {
+        if seen.insert(rule.clone()) && existing.remove(&rule).is_none() {
+            inserted.push(rule);
+        }
+    }
+    let deleted = existing.into_iter().collect::<Vec<_>>();
+
+    for ids in deleted.chunks(DELETE_CHUNK_SIZE) {
+        diesel::delete(casbin_rule.filter(id.eq_any(ids.iter().map(|(_, rule_id)| *rule_id))))
+            .execute(conn)?;
+    }
+    insert_rules(conn, &inserted)?;
+
+    let deleted = deleted
+        .into_iter()
+        .map(|(rule, _)| rule)
+        .collect::<Vec<_>>();
+    record_changes(conn, ChangeKind::Remove, &deleted)?;
+    record_changes(conn, ChangeKind::Add, &inserted)?;
+
+    Ok(SaveSummary {
+        inserted: inserted.len(),
+        deleted: deleted.len(),
+    })
+}
+
+// Opens the read transaction a paged load runs in, so every page sees the
+// table as it stood when the first page was read, and returns the policy
+// version of that snapshot. A connection dropped before `end_snapshot` is
+// discarded by the pool rather than reused with the transaction still open.
+pub(crate) fn begin_snapshot(conn: &mut Pool) -> Result<i64> {
+    read_snapshot(conn)
+        .and_then(|()| current_version(conn))
+        .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+}
+
+pub(crate) fn end_snapshot(conn: &mut Pool) -> Result<()> {
+    type Manager = <Connection as DieselConnection>::TransactionManager;
+
+    Manager::commit_transaction(&mut **conn)
+        .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+}
+
+// Postgres and MySQL read from one snapshot under REPEATABLE READ. SQLite's
+// `BEGIN` is deferred, so the first read takes a snapshot that lasts until
+// the transaction ends.
+fn read_snapshot(conn: &mut Connection) -> QueryResult<()> {
+    type Manager = <Connection as DieselConnection>::TransactionManager;
+
+    // MySQL only applies this to the next transaction.
+    #[cfg(feature = "mysql")]
+    sql_query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY").execute(conn)?;
+
+    Manager::begin_transaction(conn)?;
+
+    #[cfg(feature = "postgres")]
+    sql_query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY").execute(conn)?;
+
+    Ok(())
+}
+
+// Runs `f` in a read transaction like the one `begin_snapshot` opens.
+fn in_read_snapshot<T, E, F>(conn: &mut Connection, f: F) -> std::result::Result<T, E>
+where
+    F: FnOnce(&mut Connection) -> std::result::Result<T, E>,
+    E: From<DieselError>,
+{
+    type Manager = <Connection as DieselConnection>::TransactionManager;
+
+    read_snapshot(conn)?;
+    match f(conn) {
+        Ok(out) => Ok(Manager::commit_transaction(conn).map(|()| out)?),
+        Err(err) => {
+            let _ = Manager::rollback_transaction(conn);
+            Err(err)
+        }
+    }
+}
+
+pub(crate) fn load_policy_batch(
+    conn: &mut Pool,
+    query: &RuleQuery,
+    after_id: Option<i32>,
+    batch_size: usize,
+) -> Result<Vec<CasbinRule>> {
+    rule_batch(conn, queried_rule(query), after_id, batch_size)
+        .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+}
+
+fn rule_batch(
+    conn: &mut Connection,
+    filter: RuleFilter,
+    after_id: Option<i32>,
+    batch_size: usize,
+) -> QueryResult<Vec<CasbinRule>> {
+    use schema::casbin_rule::dsl::*;
+
+    let mut query = casbin_rule
+        .filter(filter)
+        .order(id.asc())
+        .limit(batch_size as i64)
+        .into_boxed();
+
+    if let Some(after_id) = after_id {
+        query = query.filter(id.gt(after_id));
+    }
+
+    query.load::<CasbinRule>(conn)
+}
+
+pub(crate) fn add_policy(
+    mut conn: Pool,
+    new_rule: NewCasbinRule,
+    ctx: WriteContext,
+) -> Result<bool> {
+    use schema::casbin_rule::dsl::casbin_rule;
+
+    write_transaction::<_, DieselError, _>(&mut conn, &ctx, |conn, _| {
+        let n = diesel::insert_into(casbin_rule)
+            .values(&new_rule)
+            .execute(conn)?;
+        record_changes(conn, ChangeKind::Add, std::slice::from_ref(&new_rule))?;
+
+        Ok(n == 1)
+    })
+    .map_err(|err| {
+        let err = rule_error(&mut conn, err, std::slice::from_ref(&new_rule));
+        AdapterError(Box::new(err)).into()
+    })
+}
+
+pub(crate) fn add_policies(
+    mut conn: Pool,
+    new_rules: Vec<NewCasbinRule>,
+    ctx: WriteContext,
+) -> Result<bool> {
+    write_transaction::<_, DieselError, _>(&mut conn, &ctx, |conn, _| {
+        if insert_rules(conn, &new_rules)? != new_rules.len() {
+            return Err(DieselError::RollbackTransaction);
+        }
+        record_changes(conn, ChangeKind::Add, &new_rules)?;
+
+        Ok(true)
+    })
+    .map_err(|err| AdapterError(Box::new(rule_error(&mut conn, err, &new_rules))).into())
+}
+
+// Names the rule behind a unique violation: the first of `rules` that repeats
+// an earlier one or is already stored. Other errors are passed through.
+fn rule_error(conn: &mut Connection, err: DieselError, rules: &[NewCasbinRule]) -> Error {
+    duplicate_error(err, rules, |rule| rule_exists(conn, rule).unwrap_or(false))
+}
+
+// Like `rule_error`, with `stored` telling which rules clashed with a row
+// the failed write did not remove.
+fn duplicate_error<F>(err: DieselError, rules: &[NewCasbinRule], mut stored: F) -> Error
+where
+    F: FnMut(&NewCasbinRule) -> bool,
+{
+    if !matches!(
+        err,
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)
+    ) {
+        return Error::DieselError(err);
+    }
+
+    let mut seen = HashSet::with_capacity(rules.len());
+    let duplicate = rules
+        .iter()
+        .find(|rule| !seen.insert(*rule) || stored(rule));
+
+    match duplicate.and_then(rule_line) {
+        Some((ptype, rule)) => Error::DuplicateRule { ptype, rule },
+        None => Error::DieselError(err),
+    }
+}
+
+fn rule_exists(conn: &mut Connection, rule: &NewCasbinRule) -> QueryResult<bool> {
+    use schema::casbin_rule::dsl::casbin_rule;
+
+    diesel::select(diesel::dsl::exists(casbin_rule.filter(same_rule(rule)))).get_result(conn)
+}
+
+// The rows the database considers equal to one of `rules`, as they are stored.
+fn stored_rules(
+    conn: &mut Connection,
+    rules: &[NewCasbinRule],
+) -> QueryResult<HashSet<NewCasbinRule>> {
+    use schema::casbin_rule::dsl::casbin_rule;
+
+    let mut stored = HashSet::with_capacity(rules.len());
+    for chunk in rules.chunks(INSERT_CHUNK_SIZE) {
+        stored.extend(
+            casbin_rule
+                .filter(any_rule(chunk))
+                .load::<CasbinRule>(conn)?
+                .into_iter()
+                .map(NewCasbinRule::from),
+        );
+    }
+
+    Ok(stored)
+}
+
+// Matches any of `rules`. The filters are paired up level by level, so the
+// expression stays shallow however many rules there are.
+fn any_rule(rules: &[NewCasbinRule]) -> RuleFilter {
+    let mut filters = rules.iter().map(same_rule).collect::<Vec<_>>();
+
+    while filters.len() > 1 {
+        let mut pairs = filters.into_iter();
+        let mut level = Vec::with_capacity(pairs.len().div_ceil(2));
+        while let Some(left) = pairs.next() {
+            level.push(match pairs.next() {
+                Some(right) => Box::new(left.or(right)) as RuleFilter,
+                None => left,
+            });
+        }
+        filters = level;
+    }
+
+    filters
+        .pop()
+        .unwrap_or_else(|| Box::new(false.into_sql::<Bool>()))
+}
+
+fn same_rule(rule: &NewCasbinRule) -> RuleFilter {
+    use schema::casbin_rule::dsl::*;
+
+    Box::new(
+        ptype
+            .eq(rule.ptype.clone())
+            .and(v0.eq(rule.v0.clone()))
+            .and(v1.eq(rule.v1.clone()))
+            .and(v2.eq(rule.v2.clone()))
+            .and(v3.eq(rule.v3.clone()))
+            .and(v4.eq(rule.v4.clone()))
+            .and(v5.eq(rule.v5.clone())),
+    )
+}
+
+pub(crate) fn add_policies_ignore_duplicates(
+    mut conn: Pool,
+    new_rules: Vec<NewCasbinRule>,
+    ctx: WriteContext,
+) -> Result<usize> {
+    write_transaction::<_, DieselError, _>(&mut conn, &ctx, |conn, _| {
+        let inserted = insert_missing_rules(conn, &new_rules)?;
+
+        record_changes(conn, ChangeKind::Add, &inserted)?;
+
+        Ok(inserted.len())
+    })
+    .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+}
+
+// Inserts `rules` in the order given, skipping rules that already exist.
+pub(crate) fn import_rules<I>(
+    mut conn: Pool,
+    rules: I,
+    mode: ImportMode,
+    ctx: WriteContext,
+) -> Result<SaveSummary>
+where
+    I: Iterator<Item = std::result::Result<NewCasbinRule, Error>>,
+{
+    use schema::casbin_rule::dsl::casbin_rule;
+
+    write_transaction::<_, Error, _>(&mut conn, &ctx, |conn, _| {
+        let mut summary = SaveSummary::default();
+
+        if mode == ImportMode::Replace {
+            record_table(conn, ChangeKind::Remove)?;
+            summary.deleted = diesel::delete(casbin_rule).execute(conn)?;
+        }
+
+        let mut chunk = Vec::with_capacity(INSERT_CHUNK_SIZE);
+        for rule in rules {
+            chunk.push(rule?);
+            if chunk.len() == INSERT_CHUNK_SIZE {
+                summary.inserted += import_chunk(conn, &mut chunk)?;
+            }
+        }
+        summary.inserted += import_chunk(conn, &mut chunk)?;
+
+        Ok(summary)
+    })
+    .map_err(|err| AdapterError(Box::new(err)).into())
+}
+
+// Copies every rule of `source` into `dest` in `id` order, skipping rules
+// `dest` already has, reading `source` in one snapshot. Each batch is read
+// back from `dest` once written, and the transaction is rolled back unless
+// `dest` holds every source rule, value for value.
+pub(crate) fn copy_rules(
+    mut source: Pool,
+    mut dest: Pool,
+    batch_size: usize,
+    ctx: WriteContext,
+) -> Result<CopyReport> {
+    type Manager = <Connection as DieselConnection>::TransactionManager;
+
+    write_transaction::<_, Error, _>(&mut dest, &ctx, |conn, _| {
+        let mut report = CopyReport::default();
+        let mut expected = Checksum::default();
+        let mut actual = Checksum::default();
+        let mut actual_rules = 0;
+        let mut after_id = None;
+
+        read_snapshot(&mut source)?;
+
+        loop {
+            let rules = rule_batch(
+                &mut source,
+                queried_rule(&RuleQuery::default()),
+                after_id,
+                batch_size,
+            )?;
+            let next_id = rules.last().map(|last| last.id);
+            let full = rules.len() == batch_size;
+            let rules = rules
+                .into_iter()
+                .map(NewCasbinRule::from)
+                .collect::<Vec<_>>();
+
+            let inserted = insert_missing_rules(conn, &rules)?;
+            let stored = stored_rules(conn, &rules)?;
+
+            // A row matched by a looser comparison than the source's, or cut
+            // short on insert, is missing from `stored` and fails the count.
+            for rule in &rules {
+                expected.update(rule);
+                if stored.contains(rule) {
+                    actual.update(rule);
+                    actual_rules += 1;
+                }
+            }
+
+            record_changes(conn, ChangeKind::Add, &inserted)?;
+            report.read += rules.len();
+            report.copied += inserted.len();
+            report.duplicates += rules.len() - inserted.len();
+
+            match next_id {
+                Some(next_id) if full => after_id = Some(next_id),
+                _ => break,
+            }
+        }
+
+        Manager::commit_transaction(&mut *source)?;
+
+        if actual_rules != report.read || actual.value() != expected.value() {
+            return Err(Error::CopyMismatch {
+                expected_rules: report.read,
+                actual_rules,
+                expected_checksum: expected.value(),
+                actual_checksum: actual.value(),
+            });
+        }
+
+        report.checksum = expected.value();
+
+        Ok(report)
+    })
+    .map_err(|err| AdapterError(Box::new(err)).into())
+}
+
+fn import_chunk(conn: &mut Connection, chunk: &mut Vec<NewCasbinRule>) -> QueryResult<usize> {
+    let inserted = insert_missing_rules(conn, chunk)?;
+    chunk.clear();
+
+    record_changes(conn, ChangeKind::Add, &inserted)?;
+
+    Ok(inserted.len())
+}
+
+// Inserts `rules` with one statement per chunk, skipping rules the table
+// already has, and returns the rows inserted in `id` order.
+#[cfg(feature = "postgres")]
+fn insert_missing_rules(
+    conn: &mut Connection,
+    rules: &[NewCasbinRule],
+) -> QueryResult<Vec<NewCasbinRule>> {
+    use schema::casbin_rule::dsl::casbin_rule;
+
+    let mut inserted = Vec::with_capacity(rules.len());
+    for chunk in rules.chunks(INSERT_CHUNK_SIZE) {
+        let mut rows = diesel::insert_into(casbin_rule)
+            .values(chunk)
+            .on_conflict_do_nothing()
+            .get_results::<CasbinRule>(conn)?;
+        rows.sort_by_key(|row| row.id);
+        inserted.extend(rows.into_iter().map(NewCasbinRule::from));
+    }
+
+    Ok(inserted)
+}
+
+// MySQL has no `RETURNING`, and diesel runs a SQLite batch with `RETURNING`
+// row by row, failing on the first rule skipped. Writers hold the version
+// lock, so the rows past the highest `id` before the insert are the ones it
+// added.
+#[cfg(any(feature = "mysql", feature = "sqlite"))]
+fn insert_missing_rules(
+    conn: &mut Connection,
+    rules: &[NewCasbinRule],
+) -> QueryResult<Vec<NewCasbinRule>> {
+    use schema::casbin_rule::dsl::*;
+
+    let mut inserted = Vec::with_capacity(rules.len());
+    for chunk in rules.chunks(INSERT_CHUNK_SIZE) {
+        let last_id = casbin_rule
+            .select(diesel::dsl::max(id))
+            .first::<Option<i32>>(conn)?
+            .unwrap_or(0);
+        insert_ignoring_duplicates(conn, chunk)?;
+        inserted.extend(
+            casbin_rule
+                .filter(id.gt(last_id))
+                .order(id.asc())
+                .load::<CasbinRule>(conn)?
+                .into_iter()
+                .map(NewCasbinRule::from),
+        );
+    }
+
+    Ok(inserted)
+}
+
+// Diesel only batches a plain or `OR IGNORE` insert on SQLite. No rule value
+// can be null, so only duplicates are ignored.
+#[cfg(feature = "sqlite")]
+fn insert_ignoring_duplicates(
+    conn: &mut Connection,
+    new_rules: &[NewCasbinRule],
+) -> QueryResult<()> {
+    use schema::casbin_rule::dsl::casbin_rule;
+
+    diesel::insert_or_ignore_into(casbin_rule)
+        .values(new_rules)
+        .execute(conn)
+        .map(|_| ())
+}
+
+// `INSERT IGNORE` would also turn data errors, such as a value too long for
+// its column, into warnings, so only a duplicate key is let through here.
+#[cfg(feature = "mysql")]
+fn insert_ignoring_duplicates(
+    conn: &mut Connection,
+    new_rules: &[NewCasbinRule],
+) -> QueryResult<()> {
+    use schema::casbin_rule::dsl::*;
+
+    diesel::insert_into(casbin_rule)
+        .values(new_rules)
+        .on_conflict(diesel::dsl::DuplicatedKeys)
+        .do_update()
+        .set(id.eq(id))
+        .execute(conn)
+        .map(|_| ())
+}
+
+pub(crate) fn update_policies(
+    mut conn: Pool,
+    pt: &str,
+    rules: Vec<(Vec<String>, Option<NewCasbinRule>)>,
+    ctx: WriteContext,
+) -> Result<Vec<bool>> {
+    use schema::casbin_rule::dsl::*;
+
+    write_transaction::<_, DieselError, _>(&mut conn, &ctx, |conn, _| {
+        let mut updated = Vec::with_capacity(rules.len());
+
+        for (old_rule, new_rule) in &rules {
+            let new_rule = match new_rule {
+                Some(new_rule) => new_rule,
+                None => {
+                    updated.push(false);
+                    continue;
+                }
+            };
+            let old_rule = normalize_casbin_rule(old_rule.clone());
+
+            let filter = ptype
+                .eq(pt)
+                .and(v0.eq(&old_rule[0]))
+                .and(v1.eq(&old_rule[1]))
+                .and(v2.eq(&old_rule[2]))
+                .and(v3.eq(&old_rule[3]))
+                .and(v4.eq(&old_rule[4]))
+                .and(v5.eq(&old_rule[5]));
+
+            let n = diesel::update(casbin_rule.filter(filter))
+                .set(new_rule)
+                .execute(conn)?;
+
+            if n == 1 {
+                record_changes(conn, ChangeKind::Remove, &[new_casbin_rule(pt, &old_rule)])?;
+                record_changes(conn, ChangeKind::Add, std::slice::from_ref(new_rule))?;
+            }
+
+            updated.push(n == 1);
+        }
+
+        Ok(updated)
+    })
+    .map_err(|err| {
+        let new_rules = rules
+            .into_iter()
+            .filter_map(|(_, new_rule)| new_rule)
+            .collect::<Vec<_>>();
+        AdapterError(Box::new(rule_error(&mut conn, err, &new_rules))).into()
+    })
+}
+
+pub(crate) fn update_filtered_policies(
+    mut conn: Pool,
+    pt: &str,
+    field_index: usize,
+    field_values: Vec<String>,
+    new_rules: Vec<NewCasbinRule>,
+    ctx: WriteContext,
+) -> Result<Vec<CasbinRule>> {
+    write_transaction::<_, DieselError, _>(&mut conn, &ctx, |conn, _| {
+        let old_rules = delete_filtered(conn, pt, field_index, &field_values)?;
+        insert_rules(conn, &new_rules)?;
+
+        record_changes(conn, ChangeKind::Remove, &to_new_casbin_rules(&old_rules))?;
+        record_changes(conn, ChangeKind::Add, &new_rules)?;
+
+        Ok(old_rules)
+    })
+    .map_err(|err| {
+        // The rows the filter removed are back after the rollback, but a new
+        // rule could not have clashed with them.
+        let err = duplicate_error(err, &new_rules, |rule| {
+            let filtered = rule_line(rule).is_some_and(|(ptype, values)| {
+                ptype == pt && matches_filter(&values, field_index, &field_values)
+            });
+            !filtered && rule_exists(&mut conn, rule).unwrap_or(false)
+        });
+        AdapterError(Box::new(err)).into()
+    })
+}
+
+pub(crate) fn load_audit_log(mut conn: Pool, query: AuditQuery) -> Result<Vec<CasbinAuditLog>> {
+    use schema::casbin_audit_log::dsl::*;
+
+    let mut rows = casbin_audit_log.order(id.asc()).into_boxed();
+
+    if let Some(after_id) = query.after_id {
+        rows = rows.filter(id.gt(after_id));
+    }
+    if let Some(ref name) = query.operation {
+        rows = rows.filter(operation.eq(name.clone()));
+    }
+    if let Some(ref pt) = query.ptype {
+        rows = rows.filter(ptype.eq(pt.clone()));
+    }
+    if let Some(ref who) = query.actor {
+        rows = rows.filter(actor.eq(who.clone()));
+    }
+    if let Some(since) = query.since {
+        rows = rows.filter(created_at.ge(since));
+    }
+    if let Some(until) = query.until {
+        rows = rows.filter(created_at.lt(until));
+    }
+    if let Some(limit) = query.limit {
+        rows = rows.limit(limit as i64);
+    }
+
+    rows.load::<CasbinAuditLog>(&mut conn)
+        .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+}
+
+pub(crate) fn checkpoint(mut conn: Pool) -> Result<i64> {
+    use schema::casbin_rule_change::dsl::*;
+
+    casbin_rule_change
+        .select(diesel::dsl::max(id))
+        .first::<Option<i64>>(&mut conn)
+        .map(|seq| seq.unwrap_or(0))
+        .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+}
+
+// The prune mark is read in the same snapshot as the changes, so a prune
+// cannot remove changes between the check and the read.
+pub(crate) fn load_changes(mut conn: Pool, since: i64) -> Result<Vec<CasbinRuleChange>> {
+    in_read_snapshot(&mut conn, |conn| {
+        let pruned = pruned_through(conn)?;
+        if since < pruned {
+            return Err(Error::ChangesPruned { pruned });
+        }
+
+        changes_after(conn, since).map_err(Error::from)
+    })
+    .map_err(|err| AdapterError(Box::new(err)).into())
+}
+
+// Deletes the changes up to `checkpoint` and records how far the log has
+// been pruned. The version lock keeps writers out, and the latest change is
+// always kept so the version, which is its id, stays where it is.
+pub(crate) fn prune_changes(mut conn: Pool, checkpoint: i64) -> Result<usize> {
+    use schema::{casbin_rule_change, casbin_rule_change_pruned};
+
+    conn.transaction::<_, DieselError, _>(|conn| {
+        let through = checkpoint.min(lock_version(conn)? - 1);
+        if through <= pruned_through(conn)? {
+            return Ok(0);
+        }
+
+        let pruned =
+            diesel::delete(casbin_rule_change::table.filter(casbin_rule_change::id.le(through)))
+                .execute(conn)?;
+        diesel::update(casbin_rule_change_pruned::table.find(1))
+            .set(casbin_rule_change_pruned::pruned_through.eq(through))
+            .execute(conn)?;
+
+        Ok(pruned)
+    })
+    .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+}
+
+// The id of the last change pruned from the change log, or 0.
+fn pruned_through(conn: &mut Connection) -> QueryResult<i64> {
+    use schema::casbin_rule_change_pruned::dsl::*;
+
+    casbin_rule_change_pruned
+        .select(pruned_through)
+        .find(1)
+        .first::<i64>(conn)
+}
+
+fn changes_after(conn: &mut Connection, since: i64) -> QueryResult<Vec<CasbinRuleChange>> {
+    use schema::casbin_rule_change::dsl::*;
+
+    casbin_rule_change
+        .filter(id.gt(since))
+        .order(id.asc())
+        .load::<CasbinRuleChange>(conn)
+}
+
+pub(crate) fn policy_version(conn: &mut Pool) -> Result<i64> {
+    current_version(conn).map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+}
+
+#[cfg(feature = "postgres")]
+pub(crate) fn listen(conn: &mut Pool) -> Result<()> {
+    sql_query(format!("LISTEN {NOTIFY_CHANNEL}"))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+}
+
+#[cfg(feature = "postgres")]
+pub(crate) fn unlisten(conn: &mut Pool) -> Result<()> {
+    sql_query("UNLISTEN *")
+        .execute(conn)
+        .map(|_| ())
+        .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+}
+
+#[cfg(feature = "postgres")]
+pub(crate) fn notifications(conn: &mut Pool) -> Result<Vec<String>> {
+    conn.notifications_iter()
+        .map(|notification| notification.map(|n| n.payload))
+        .collect::<QueryResult<Vec<_>>>()
+        .map_err(|err| AdapterError(Box::new(Error::DieselError(err))).into())
+}
+
+// Every write runs in one of these. Locking the version row until commit
+// serializes writers, which keeps change log ids visible in increasing order:
+// otherwise a reader could move its checkpoint past a change that has not
+// committed yet. `f` is given the version the write started from. On Postgres
+// the write sends one notification, just before it commits, if it moved the
+// version.
+fn write_transaction<T, E, F>(
+    conn: &mut Pool,
+    ctx: &WriteContext,
+    f: F,
+) -> std::result::Result<T, E>
+where
+    F: FnOnce(&mut Connection, i64) -> std::result::Result<T, E>,
+    E: From<DieselError>,
+{
+    let write = |conn: &mut Connection| {
+        let version = lock_version(conn)?;
+        let out = f(conn, version)?;
+
+        if let Some(ref audit) = ctx.audit {
+            record_audit(conn, audit, version)?;
+        }
+        notify_version(conn, version)?;
+
+        let changes = match ctx.changes {
+            Some(_) => Some(changes_after(conn, version)?),
+            None => None,
+        };
+
+        Ok((out, changes))
+    };
+
+    let conn: &mut Connection = conn;
+    let (out, changes) = if ctx.dry_run {
+        rolled_back::<_, E, _>(conn, write)?
+    } else {
+        conn.transaction::<_, E, _>(write)?
+    };
+
+    if let (Some(sink), Some(changes)) = (&ctx.changes, changes) {
+        *sink.lock().unwrap() = changes;
+    }
+
+    Ok(out)
+}
+
+// Runs `f` in a transaction that is rolled back whether or not it fails. The
+// rows it wrote, the change log entries and, on Postgres, the notification
+// all go with it.
+fn rolled_back<T, E, F>(conn: &mut Connection, f: F) -> std::result::Result<T, E>
+where
+    F: FnOnce(&mut Connection) -> std::result::Result<T, E>,
+    E: From<DieselError>,
+{
+    type Manager = <Connection as DieselConnection>::TransactionManager;
+
+    Manager::begin_transaction(conn)?;
+    let out = f(conn);
+    Manager::rollback_transaction(conn)?;
+
+    out
+}
+
+fn lock_version(conn: &mut Connection) -> QueryResult<i64> {
+    sql_query(format!(
+        "UPDATE {VERSION_TABLE_NAME} SET version = version WHERE id = 1"
+    ))
+    .execute(conn)?;
+
+    current_version(conn)
+}
+
+fn current_version(conn: &mut Connection) -> QueryResult<i64> {
+    use schema::casbin_policy_version::dsl::*;
+
+    casbin_policy_version
+        .select(version)
+        .find(1)
+        .first::<i64>(conn)
+}
+
+// The version is the id of the latest change, so a write that changed nothing
+// leaves it alone.
+fn touch_version(conn: &mut Connection) -> QueryResult<()> {
+    sql_query(format!(
+        "UPDATE {VERSION_TABLE_NAME} SET version = (SELECT COALESCE(MAX(id), 0) FROM {CHANGE_TABLE_NAME}) WHERE id = 1"
+    ))
+    .execute(conn)
+    .map(|_| ())
+}
+
+// Postgres holds the notification back until the transaction commits and
+// drops it on rollback, so listeners never hear about uncommitted writes.
+#[cfg(feature = "postgres")]
+fn notify_version(conn: &mut Connection, previous: i64) -> QueryResult<()> {
+    let version = current_version(conn)?;
+    if version == previous {
+        return Ok(());
+    }
+
+    let update = PolicyUpdate {
+        checkpoint: previous,
+        version,
+    };
+
+    sql_query("SELECT pg_notify($1, $2)")
+        .bind::<Varchar, _>(NOTIFY_CHANNEL)
+        .bind::<Varchar, _>(update.to_string())
+        .execute(conn)
+        .map(|_| ())
+}
+
+#[cfg(not(feature = "postgres"))]
+fn notify_version(_conn: &mut Connection, _previous: i64) -> QueryResult<()> {
+    Ok(())
+}
+
+fn record_changes(
+    conn: &mut Connection,
+    kind: ChangeKind,
+    rules: &[NewCasbinRule],
+) -> QueryResult<()> {
+    use schema::casbin_rule_change::dsl::casbin_rule_change;
+
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    let created_at = now_millis(conn)?;
+    let changes = rules
+        .iter()
+        .map(|rule| NewCasbinRuleChange {
+            op: kind.as_str(),
+            ptype: &rule.ptype,
+            v0: &rule.v0,
+            v1: &rule.v1,
+            v2: &rule.v2,
+            v3: &rule.v3,
+            v4: &rule.v4,
+            v5: &rule.v5,
+            created_at,
+        })
+        .collect::<Vec<_>>();
+
+    for chunk in changes.chunks(CHANGE_CHUNK_SIZE) {
+        diesel::insert_into(casbin_rule_change)
+            .values(chunk)
+            .execute(conn)?;
+    }
+
+    touch_version(conn)
+}
+
+// Splits the rows into as many statements as the backend's bind parameter
+// limit requires. Callers are expected to be inside a transaction.
+fn insert_rules(conn: &mut Connection, rules: &[NewCasbinRule]) -> QueryResult<usize> {
+    use schema::casbin_rule::dsl::casbin_rule;
+
+    let mut n = 0;
+    for chunk in rules.chunks(INSERT_CHUNK_SIZE) {
+        n += diesel::insert_into(casbin_rule)
+            .values(chunk)
+            .execute(conn)?;
+    }
+
+    Ok(n)
+}
+
+// Copies the changes made since `version` by the current transaction into the
+// audit log.
+fn record_audit(conn: &mut Connection, audit: &Audit, version: i64) -> QueryResult<()> {
+    use schema::{casbin_audit_log, casbin_rule_change};
+
+    let rows = casbin_rule_change::table
+        .filter(casbin_rule_change::id.gt(version))
+        .order(casbin_rule_change::id.asc())
+        .select((
+            audit.operation.into_sql::<Varchar>(),
+            casbin_rule_change::op,
+            casbin_rule_change::ptype,
+            casbin_rule_change::v0,
+            casbin_rule_change::v1,
+            casbin_rule_change::v2,
+            casbin_rule_change::v3,
+            casbin_rule_change::v4,
+            casbin_rule_change::v5,
+            audit.actor.as_str().into_sql::<Varchar>(),
+            casbin_rule_change::created_at,
+        ));
+
+    diesel::insert_into(casbin_audit_log::table)
+        .values(rows)
+        .into_columns((
+            casbin_audit_log::operation,
+            casbin_audit_log::op,
+            casbin_audit_log::ptype,
+            casbin_audit_log::v0,
+            casbin_audit_log::v1,
+            casbin_audit_log::v2,
+            casbin_audit_log::v3,
+            casbin_audit_log::v4,
+            casbin_audit_log::v5,
+            casbin_audit_log::actor,
+            casbin_audit_log::created_at,
+        ))
+        .execute(conn)
+        .map(|_| ())
+}
+
+// Records every row currently in the rule table, without loading it.
+fn record_table(conn: &mut Connection, kind: ChangeKind) -> QueryResult<()> {
+    use schema::{casbin_rule, casbin_rule_change};
+
+    let created_at = now_millis(conn)?;
+    let rows = casbin_rule::table.order(casbin_rule::id.asc()).select((
+        kind.as_str().into_sql::<Varchar>(),
+        casbin_rule::ptype,
+        casbin_rule::v0,
+        casbin_rule::v1,
+        casbin_rule::v2,
+        casbin_rule::v3,
+        casbin_rule::v4,
+        casbin_rule::v5,
+        created_at.into_sql::<BigInt>(),
+    ));
+
+    diesel::insert_into(casbin_rule_change::table)
+        .values(rows)
+        .into_columns((
+            casbin_rule_change::op,
+            casbin_rule_change::ptype,
+            casbin_rule_change::v0,
+            casbin_rule_change::v1,
+            casbin_rule_change::v2,
+            casbin_rule_change::v3,
+            casbin_rule_change::v4,
+            casbin_rule_change::v5,
+            casbin_rule_change::created_at,
+        ))
+        .execute(conn)?;
+
+    touch_version(conn)
+}
+
+// Milliseconds since the Unix epoch by the database's clock, so writers on
+// different hosts stamp their changes from the same clock.
+fn now_millis(conn: &mut Connection) -> QueryResult<i64> {
+    #[cfg(feature = "postgres")]
+    let now = "CAST(EXTRACT(EPOCH FROM clock_timestamp()) * 1000 AS BIGINT)";
+    #[cfg(feature = "mysql")]
+    let now = "CAST(UNIX_TIMESTAMP(NOW(3)) * 1000 AS SIGNED)";
+    #[cfg(feature = "sqlite")]
+    let now = "CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)";
+
+    diesel::select(diesel::dsl::sql::<BigInt>(now)).get_result(conn)
+}
+
+fn new_casbin_rule<S: AsRef<str>>(pt: &str, rule: &[S]) -> NewCasbinRule {
+    NewCasbinRule {
+        ptype: pt.to_owned(),
+        v0: rule[0].as_ref().to_owned(),
+        v1: rule[1].as_ref().to_owned(),
+        v2: rule[2].as_ref().to_owned(),
+        v3: rule[3].as_ref().to_owned(),
+        v4: rule[4].as_ref().to_owned(),
+        v5: rule[5].as_ref().to_owned(),
+    }
+}
+
+fn to_new_casbin_rules(rules: &[CasbinRule]) -> Vec<NewCasbinRule> {
+    rules
+        .iter()
+        .map(|r| new_casbin_rule(&r.ptype, &[&r.v0, &r.v1, &r.v2, &r.v3, &r.v4, &r.v5]))
+        .collect()
 }
 
-fn normalize_casbin_rule(mut rule: Vec<String>, field_index: usize) -> Vec<String> {
-    rule.resize(6 - field_index, String::from(""));
+fn normalize_casbin_rule(mut rule: Vec<String>) -> Vec<String> {
+    rule.resize(6, String::from(""));
     rule
 }
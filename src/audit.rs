@@ -0,0 +1,38 @@
+use crate::changes::ChangeKind;
+
+/// Who made a write and through which method, for the audit log.
+pub(crate) struct Audit {
+    pub operation: &'static str,
+    pub actor: String,
+}
+
+/// A rule added or removed by a write made while the audit log was enabled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// The adapter method that made the write, such as `"add_policy"`.
+    pub operation: String,
+    pub kind: ChangeKind,
+    pub ptype: String,
+    pub rule: Vec<String>,
+    /// The actor set on the adapter when the write was made.
+    pub actor: String,
+    /// Milliseconds since the Unix epoch.
+    pub created_at: i64,
+}
+
+/// Filters for reading the audit log. Every field left as `None` matches
+/// all entries.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditQuery {
+    pub operation: Option<String>,
+    pub ptype: Option<String>,
+    pub actor: Option<String>,
+    /// Only entries created at or after this many milliseconds since the Unix epoch.
+    pub since: Option<i64>,
+    /// Only entries created before this many milliseconds since the Unix epoch.
+    pub until: Option<i64>,
+    /// Only entries after this id, for paging through the log.
+    pub after_id: Option<i64>,
+    pub limit: Option<usize>,
+}
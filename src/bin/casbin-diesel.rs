@@ -0,0 +1,396 @@
+//! Inspects and edits the policy table of a `DieselAdapter` database.
+//!
+//! Every command connects to `--database-url`, or `DATABASE_URL`, and creates
+//! the adapter's tables if they are missing. Results are printed as text, or
+//! as JSON with `--json`.
+//!
+//! `copy` only reaches databases of the backend the tool was built for. To
+//! move rules between backends, pipe a build for one into a build for the
+//! other:
+//!
+//! ```text
+//! casbin-diesel --database-url mysql://... export --format json \
+//!     | casbin-diesel --database-url postgres://... import - --format json
+//! ```
+
+use clap::{Parser, Subcommand, ValueEnum};
+use diesel_adapter::{
+    casbin::Adapter, DieselAdapter, ImportMode, PolicyDocument, RuleQuery, SaveSummary,
+};
+use serde_json::{json, Value};
+
+use std::{
+    error::Error as StdError,
+    fs::File,
+    future::Future,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn StdError>>;
+
+#[derive(Parser)]
+#[command(
+    name = "casbin-diesel",
+    version,
+    about = "Inspect and edit a casbin policy table"
+)]
+struct Cli {
+    /// The database to connect to.
+    #[arg(long, env = "DATABASE_URL", hide_env_values = true)]
+    database_url: String,
+
+    /// Print results as JSON.
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List every rule, or every rule of one ptype.
+    List {
+        #[arg(long)]
+        ptype: Option<String>,
+    },
+    /// Find the rules holding a value, in any field or in the field given.
+    Search {
+        value: String,
+        #[arg(long)]
+        ptype: Option<String>,
+        #[arg(long)]
+        field_index: Option<usize>,
+    },
+    /// Add a rule.
+    Add {
+        ptype: String,
+        #[arg(required = true)]
+        values: Vec<String>,
+    },
+    /// Remove a rule.
+    Remove {
+        ptype: String,
+        #[arg(required = true)]
+        values: Vec<String>,
+    },
+    /// Remove the rules whose values match from FIELD_INDEX on. An empty value
+    /// matches anything.
+    RemoveFiltered {
+        ptype: String,
+        field_index: usize,
+        #[arg(required = true)]
+        values: Vec<String>,
+    },
+    /// Import rules from a file, or from stdin with `-`, skipping rules that
+    /// already exist.
+    Import {
+        file: PathBuf,
+        /// Defaults to the file's extension, or CSV.
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+        /// Delete every existing rule first.
+        #[arg(long)]
+        replace: bool,
+    },
+    /// Export every rule to a file, or to stdout.
+    Export {
+        file: Option<PathBuf>,
+        /// Defaults to the file's extension, or CSV.
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+    },
+    /// Create the adapter's tables if they are missing.
+    Migrate,
+    /// Copy every rule into another database of the same backend. Pipe
+    /// `export` into `import -` to copy across backends.
+    Copy {
+        /// The database to copy into.
+        #[arg(long)]
+        to: String,
+        /// How many rules to read from this database per query.
+        #[arg(long)]
+        batch_size: Option<usize>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    Json,
+    Yaml,
+}
+
+impl Format {
+    fn resolve(format: Option<Format>, file: Option<&Path>) -> Format {
+        let extension = file
+            .and_then(Path::extension)
+            .and_then(|extension| extension.to_str());
+
+        match (format, extension) {
+            (Some(format), _) => format,
+            (None, Some("json")) => Format::Json,
+            (None, Some("yaml")) | (None, Some("yml")) => Format::Yaml,
+            (None, _) => Format::Csv,
+        }
+    }
+}
+
+// What a command prints: `json` with `--json`, `text` otherwise.
+struct Output {
+    json: Value,
+    text: String,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let json = cli.json;
+
+    match block_on(run(cli)) {
+        Ok(Some(output)) if json => println!("{:#}", output.json),
+        Ok(Some(output)) => print!("{}", output.text),
+        Ok(None) => {}
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<Option<Output>> {
+    let mut adapter = DieselAdapter::new(cli.database_url, 1)?;
+
+    let output = match cli.command {
+        Command::List { ptype } => {
+            let query = RuleQuery {
+                ptype,
+                ..RuleQuery::default()
+            };
+            return list(&adapter, query, cli.json).await;
+        }
+        Command::Search {
+            value,
+            ptype,
+            field_index,
+        } => {
+            let query = RuleQuery {
+                ptype,
+                value: Some(value),
+                field_index,
+            };
+            return list(&adapter, query, cli.json).await;
+        }
+        Command::Add { ptype, values } => {
+            let line = rule_line(&ptype, &values);
+            let added = adapter
+                .add_policy_ignore_duplicates(section(&ptype), &ptype, values.clone())
+                .await?
+                == 1;
+
+            Output {
+                json: json!({ "ptype": ptype, "rule": values, "added": added }),
+                text: if added {
+                    format!("added: {}\n", line)
+                } else {
+                    format!("already exists: {}\n", line)
+                },
+            }
+        }
+        Command::Remove { ptype, values } => {
+            let line = rule_line(&ptype, &values);
+            let removed = adapter
+                .remove_policy(section(&ptype), &ptype, values.clone())
+                .await?;
+
+            Output {
+                json: json!({ "ptype": ptype, "rule": values, "removed": removed }),
+                text: if removed {
+                    format!("removed: {}\n", line)
+                } else {
+                    format!("not found: {}\n", line)
+                },
+            }
+        }
+        Command::RemoveFiltered {
+            ptype,
+            field_index,
+            values,
+        } => {
+            let removed = adapter
+                .remove_filtered_policy_returning(section(&ptype), &ptype, field_index, values)
+                .await?;
+            let text = removed
+                .iter()
+                .map(|rule| format!("removed: {}\n", rule_line(&ptype, rule)))
+                .collect::<String>();
+
+            Output {
+                json: json!({ "ptype": ptype, "removed": removed }),
+                text: format!("{}{} rules removed\n", text, removed.len()),
+            }
+        }
+        Command::Import {
+            file,
+            format,
+            replace,
+        } => {
+            let mode = if replace {
+                ImportMode::Replace
+            } else {
+                ImportMode::Merge
+            };
+            let summary = if file == Path::new("-") {
+                let format = Format::resolve(format, None);
+                import(&mut adapter, io::stdin(), format, mode).await?
+            } else {
+                let format = Format::resolve(format, Some(&file));
+                import(&mut adapter, File::open(&file)?, format, mode).await?
+            };
+
+            Output {
+                json: json!({ "inserted": summary.inserted, "deleted": summary.deleted }),
+                text: format!(
+                    "{} rules inserted, {} deleted\n",
+                    summary.inserted, summary.deleted
+                ),
+            }
+        }
+        Command::Export { file, format } => {
+            let format = Format::resolve(format, file.as_deref());
+
+            // Exporting to stdout prints nothing else, so the output can be
+            // piped as it is.
+            let file = match file {
+                Some(file) => file,
+                None => {
+                    export(&adapter, &mut io::stdout().lock(), format).await?;
+                    return Ok(None);
+                }
+            };
+
+            let exported = export(&adapter, &mut File::create(&file)?, format).await?;
+
+            Output {
+                json: json!({ "exported": exported, "file": file }),
+                text: format!("{} rules exported to {}\n", exported, file.display()),
+            }
+        }
+        Command::Migrate => Output {
+            json: json!({ "migrated": true }),
+            text: "the policy tables are up to date\n".to_owned(),
+        },
+        Command::Copy { to, batch_size } => {
+            let mut dest = DieselAdapter::new(to, 1)?;
+            if let Some(batch_size) = batch_size {
+                adapter.set_load_batch_size(batch_size);
+            }
+            let report = dest.copy_policy_from(&adapter).await?;
+
+            Output {
+                json: json!({
+                    "read": report.read,
+                    "copied": report.copied,
+                    "duplicates": report.duplicates,
+                    "checksum": format!("{:016x}", report.checksum),
+                }),
+                text: format!(
+                    "{} rules read, {} copied, {} already present; checksum {:016x} verified\n",
+                    report.read, report.copied, report.duplicates, report.checksum
+                ),
+            }
+        }
+    };
+
+    Ok(Some(output))
+}
+
+async fn import<R: Read + Send + 'static>(
+    adapter: &mut DieselAdapter,
+    reader: R,
+    format: Format,
+    mode: ImportMode,
+) -> Result<SaveSummary> {
+    Ok(match format {
+        Format::Csv => adapter.import_csv(reader, mode).await?,
+        Format::Json => adapter.import_json(reader, mode).await?,
+        Format::Yaml => adapter.import_yaml(reader, mode).await?,
+    })
+}
+
+async fn export<W: Write>(
+    adapter: &DieselAdapter,
+    writer: &mut W,
+    format: Format,
+) -> Result<usize> {
+    Ok(match format {
+        Format::Csv => adapter.export_csv(writer).await?,
+        Format::Json => adapter.export_json(writer).await?,
+        Format::Yaml => adapter.export_yaml(writer).await?,
+    })
+}
+
+// Prints the rules matching `query`. Text is printed as the rules are read;
+// JSON groups them by ptype, so the matches are collected first.
+async fn list(adapter: &DieselAdapter, query: RuleQuery, json: bool) -> Result<Option<Output>> {
+    if json {
+        let mut document = PolicyDocument::default();
+        adapter
+            .for_each_rule(query, |ptype, rule| {
+                document
+                    .policies
+                    .entry(ptype.to_owned())
+                    .or_default()
+                    .push(rule)
+            })
+            .await?;
+
+        return Ok(Some(Output {
+            json: serde_json::to_value(&document).unwrap_or(Value::Null),
+            text: String::new(),
+        }));
+    }
+
+    let mut stdout = io::stdout().lock();
+    let mut failed = None;
+    adapter
+        .for_each_rule(query, |ptype, rule| {
+            if failed.is_none() {
+                failed = writeln!(stdout, "{}", rule_line(ptype, &rule)).err();
+            }
+        })
+        .await?;
+
+    match failed {
+        Some(err) => Err(err.into()),
+        None => Ok(None),
+    }
+}
+
+fn rule_line(ptype: &str, rule: &[String]) -> String {
+    format!("{}, {}", ptype, rule.join(", "))
+}
+
+// The adapter files rules by ptype alone, so the section only needs to be
+// plausible.
+fn section(ptype: &str) -> &'static str {
+    if ptype.starts_with('g') {
+        "g"
+    } else {
+        "p"
+    }
+}
+
+#[cfg(feature = "runtime-tokio")]
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .expect("failed to start the tokio runtime")
+        .block_on(future)
+}
+
+#[cfg(feature = "runtime-async-std")]
+fn block_on<F: Future>(future: F) -> F::Output {
+    async_std::task::block_on(future)
+}
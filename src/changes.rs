@@ -0,0 +1,199 @@
+use casbin::Model;
+
+use std::{collections::HashMap, fmt};
+
+use crate::adapter::ptype_sections;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Add,
+    Remove,
+}
+
+impl ChangeKind {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Add => "add",
+            ChangeKind::Remove => "remove",
+        }
+    }
+
+    pub(crate) fn from_str(op: &str) -> Option<Self> {
+        match op {
+            "add" => Some(ChangeKind::Add),
+            "remove" => Some(ChangeKind::Remove),
+            _ => None,
+        }
+    }
+}
+
+/// A single row of the adapter's change log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyChange {
+    /// Position in the change log, increasing with every recorded change.
+    pub seq: i64,
+    pub kind: ChangeKind,
+    pub ptype: String,
+    pub rule: Vec<String>,
+    /// Milliseconds since the Unix epoch.
+    pub created_at: i64,
+}
+
+/// Changes recorded after a checkpoint, in the order they were made.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PolicyChanges {
+    /// Checkpoint to pass on the next call to pick up where this one stopped.
+    pub checkpoint: i64,
+    pub changes: Vec<PolicyChange>,
+}
+
+impl PolicyChanges {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn added(&self) -> impl Iterator<Item = &PolicyChange> {
+        self.changes.iter().filter(|c| c.kind == ChangeKind::Add)
+    }
+
+    pub fn removed(&self) -> impl Iterator<Item = &PolicyChange> {
+        self.changes.iter().filter(|c| c.kind == ChangeKind::Remove)
+    }
+
+    /// Replays the changes against `m` in order.
+    ///
+    /// Role links are not rebuilt; call `build_role_links` afterwards if any
+    /// `g` rule changed.
+    pub fn apply(&self, m: &mut dyn Model) {
+        let sections = ptype_sections(m);
+
+        for change in &self.changes {
+            let sec = match sections.get(&change.ptype) {
+                Some(sec) => sec,
+                None => continue,
+            };
+
+            match change.kind {
+                ChangeKind::Add => {
+                    m.add_policy(sec, &change.ptype, change.rule.clone());
+                }
+                ChangeKind::Remove => {
+                    m.remove_policy(sec, &change.ptype, change.rule.clone());
+                }
+            }
+        }
+    }
+}
+
+/// A committed write that moved the policy version from `checkpoint` to
+/// `version`.
+///
+/// Watchers pass it to the update callback in its string form,
+/// `"<checkpoint>:<version>"`. Passing `checkpoint` to `changes_since` or
+/// `load_incremental_policy` picks up the rules the write changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PolicyUpdate {
+    pub checkpoint: i64,
+    pub version: i64,
+}
+
+impl PolicyUpdate {
+    /// Parses the string a watcher passes to the update callback.
+    pub fn parse(payload: &str) -> Option<Self> {
+        let (checkpoint, version) = payload.split_once(':')?;
+
+        Some(PolicyUpdate {
+            checkpoint: checkpoint.parse().ok()?,
+            version: version.parse().ok()?,
+        })
+    }
+}
+
+impl fmt::Display for PolicyUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.checkpoint, self.version)
+    }
+}
+
+/// Rows written by `save_policy_diff`, `replace_filtered_policy` and the
+/// restore and import methods.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SaveSummary {
+    pub inserted: usize,
+    pub deleted: usize,
+}
+
+/// The rules a write would insert and delete, as previewed by a dry run.
+///
+/// A rule the write deletes and inserts again, as `save_policy` does with
+/// every rule it keeps, is in neither list.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PolicyDiff {
+    pub inserted: Vec<(String, Vec<String>)>,
+    pub deleted: Vec<(String, Vec<String>)>,
+}
+
+impl PolicyDiff {
+    pub fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.deleted.is_empty()
+    }
+
+    // Nets the changes out per rule, keeping the order in which rules were
+    // first changed.
+    pub(crate) fn from_changes<I>(changes: I) -> Self
+    where
+        I: IntoIterator<Item = PolicyChange>,
+    {
+        let mut net: HashMap<(String, Vec<String>), (usize, i64)> = HashMap::new();
+
+        for (i, change) in changes.into_iter().enumerate() {
+            let delta = match change.kind {
+                ChangeKind::Add => 1,
+                ChangeKind::Remove => -1,
+            };
+            net.entry((change.ptype, change.rule)).or_insert((i, 0)).1 += delta;
+        }
+
+        let mut net = net.into_iter().collect::<Vec<_>>();
+        net.sort_by_key(|(_, (first, _))| *first);
+
+        let mut diff = PolicyDiff::default();
+        for (line, (_, delta)) in net {
+            match delta {
+                0 => {}
+                d if d > 0 => diff.inserted.push(line),
+                _ => diff.deleted.push(line),
+            }
+        }
+
+        diff
+    }
+}
+
+/// How `remove_policies` treats rules that do not exist.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RemovalMode {
+    /// Any missing rule rolls the whole batch back.
+    #[default]
+    Strict,
+    /// Missing rules are skipped and the rest are removed.
+    BestEffort,
+}
+
+/// Outcome of a batch removal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchRemoval {
+    pub removed: Vec<Vec<String>>,
+    pub missing: Vec<Vec<String>>,
+}
+
+/// How an import treats the rules already in the table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Imported rules are added next to the existing ones; rules that already
+    /// exist are skipped.
+    #[default]
+    Merge,
+    /// The existing rules are removed first.
+    Replace,
+}
@@ -0,0 +1,53 @@
+use crate::models::NewCasbinRule;
+
+/// What `copy_policy_from` read, wrote and verified.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CopyReport {
+    /// Rules read from the source adapter.
+    pub read: usize,
+    /// Rules written to the destination, in the source's `id` order.
+    pub copied: usize,
+    /// Source rules the destination already had, which were not copied.
+    pub duplicates: usize,
+    /// FNV-1a checksum of every source rule, in `id` order. The destination's
+    /// stored copies of those rules hashed to the same value.
+    pub checksum: u64,
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+// A 64-bit FNV-1a hash over a sequence of rules. Every field is followed by a
+// byte that never occurs in UTF-8, so field boundaries cannot shift.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Checksum(u64);
+
+impl Default for Checksum {
+    fn default() -> Self {
+        Checksum(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Checksum {
+    pub(crate) fn update(&mut self, rule: &NewCasbinRule) {
+        let fields = [
+            &rule.ptype,
+            &rule.v0,
+            &rule.v1,
+            &rule.v2,
+            &rule.v3,
+            &rule.v4,
+            &rule.v5,
+        ];
+
+        for field in fields.iter() {
+            for &byte in field.as_bytes().iter().chain(&[0xff]) {
+                self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+            }
+        }
+    }
+
+    pub(crate) fn value(&self) -> u64 {
+        self.0
+    }
+}
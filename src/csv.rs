@@ -0,0 +1,171 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use crate::{error::Error, models::NewCasbinRule};
+
+// The most values a row has room for, after the ptype.
+pub(crate) const MAX_VALUES: usize = 6;
+
+const UNTERMINATED: &str = "unterminated quoted field";
+
+/// Reads rules from casbin's CSV policy format, one record at a time.
+///
+/// Each line is a ptype followed by its values, separated by commas. Blank
+/// lines and lines starting with `#` are skipped. A field may be quoted with
+/// `"`, in which case it may hold commas and line breaks, and `""` stands for
+/// one quote.
+pub(crate) struct CsvRules<R> {
+    reader: BufReader<R>,
+    line: usize,
+}
+
+impl<R: Read> CsvRules<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            line: 0,
+        }
+    }
+
+    // Appends the next line to `record`, line break included, and returns
+    // whether there was one.
+    fn read_line(&mut self, record: &mut String) -> io::Result<bool> {
+        let read = self.reader.read_line(record)?;
+        self.line += 1;
+
+        Ok(read > 0)
+    }
+}
+
+impl<R: Read> Iterator for CsvRules<R> {
+    type Item = Result<NewCasbinRule, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut record = String::new();
+            match self.read_line(&mut record) {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(err) => return Some(Err(Error::Io(err))),
+            }
+            let line = self.line;
+
+            // A quoted field left open runs on into the next line.
+            let parsed = loop {
+                match parse_record(&record) {
+                    Err(UNTERMINATED) => match self.read_line(&mut record) {
+                        Ok(true) => {}
+                        Ok(false) => break Err(UNTERMINATED),
+                        Err(err) => return Some(Err(Error::Io(err))),
+                    },
+                    parsed => break parsed,
+                }
+            };
+
+            let invalid = |reason: &str| Error::InvalidCsv {
+                line,
+                reason: reason.to_owned(),
+            };
+
+            let fields = match parsed {
+                Ok(Some(fields)) => fields,
+                Ok(None) => continue,
+                Err(reason) => return Some(Err(invalid(reason))),
+            };
+
+            return Some(match fields.split_first() {
+                Some((ptype, _)) if ptype.is_empty() => Err(invalid("missing ptype")),
+                Some((_, [])) => Err(invalid("missing values")),
+                Some((_, values)) if values.len() > MAX_VALUES => {
+                    Err(invalid("more than 6 values"))
+                }
+                Some((ptype, values)) => Ok(new_rule(ptype, values)),
+                None => continue,
+            });
+        }
+    }
+}
+
+// Splits a record into trimmed fields, or returns `None` for blank and comment
+// lines.
+fn parse_record(line: &str) -> Result<Option<Vec<String>>, &'static str> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let mut field = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next() {
+                    Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err(UNTERMINATED),
+                }
+            }
+
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            if !matches!(chars.peek(), None | Some(',')) {
+                return Err("unexpected character after quoted field");
+            }
+        } else {
+            while let Some(c) = chars.next_if(|&c| c != ',') {
+                if c == '"' {
+                    return Err("unexpected quote in unquoted field");
+                }
+                field.push(c);
+            }
+            field.truncate(field.trim_end().len());
+        }
+
+        fields.push(field);
+
+        if chars.next().is_none() {
+            return Ok(Some(fields));
+        }
+    }
+}
+
+pub(crate) fn write_line<W: Write>(
+    writer: &mut W,
+    ptype: &str,
+    values: &[String],
+) -> io::Result<()> {
+    write_field(writer, ptype)?;
+    for value in values {
+        writer.write_all(b", ")?;
+        write_field(writer, value)?;
+    }
+    writer.write_all(b"\n")
+}
+
+fn write_field<W: Write>(writer: &mut W, field: &str) -> io::Result<()> {
+    let needs_quotes =
+        field.starts_with('#') || field.trim() != field || field.contains([',', '"', '\n', '\r']);
+
+    if needs_quotes {
+        write!(writer, "\"{}\"", field.replace('"', "\"\""))
+    } else {
+        writer.write_all(field.as_bytes())
+    }
+}
+
+pub(crate) fn new_rule(ptype: &str, values: &[String]) -> NewCasbinRule {
+    let value = |i: usize| values.get(i).cloned().unwrap_or_default();
+
+    NewCasbinRule {
+        ptype: ptype.to_owned(),
+        v0: value(0),
+        v1: value(1),
+        v2: value(2),
+        v3: value(3),
+        v4: value(4),
+        v5: value(5),
+    }
+}
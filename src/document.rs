@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+
+use crate::{
+    csv::{self, MAX_VALUES},
+    error::Error,
+    models::NewCasbinRule,
+};
+
+/// Every rule grouped by ptype, in the shape `export_json` and `export_yaml`
+/// write and `import_json` and `import_yaml` read.
+///
+/// It serializes as a plain map, e.g. `{"p": [["alice", "data1", "read"]]}`.
+/// Rules keep their `id` order within each ptype. A row has six value
+/// columns, so a rule may hold one to six values; longer rules fail the
+/// import with `Error::RuleTooLong`. Empty values are kept, trailing ones only
+/// once the adapter knows the model's field counts (see `export_document`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PolicyDocument {
+    pub policies: BTreeMap<String, Vec<Vec<String>>>,
+}
+
+impl PolicyDocument {
+    /// The number of rules across all ptypes.
+    pub fn len(&self) -> usize {
+        self.policies.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn push(&mut self, ptype: &str, rule: Vec<String>) {
+        self.policies
+            .entry(ptype.to_owned())
+            .or_default()
+            .push(rule);
+    }
+
+    // Yields the rules in ptype order, failing on the first one that does not
+    // fit a row.
+    pub(crate) fn into_rules(self) -> impl Iterator<Item = Result<NewCasbinRule, Error>> {
+        self.policies.into_iter().flat_map(|(ptype, rules)| {
+            rules.into_iter().enumerate().map(move |(index, rule)| {
+                let invalid = |reason: &str| Error::InvalidDocument {
+                    ptype: ptype.clone(),
+                    index,
+                    reason: reason.to_owned(),
+                };
+
+                if ptype.is_empty() {
+                    Err(invalid("missing ptype"))
+                } else if rule.is_empty() {
+                    Err(invalid("missing values"))
+                } else if rule.len() > MAX_VALUES {
+                    Err(Error::RuleTooLong {
+                        ptype: ptype.clone(),
+                        rule,
+                    })
+                } else {
+                    Ok(csv::new_rule(&ptype, &rule))
+                }
+            })
+        })
+    }
+}
@@ -1,11 +1,102 @@
 use diesel::{r2d2::PoolError, result::Error as DieselError};
 
-use std::{error::Error as StdError, fmt};
+use std::{error::Error as StdError, fmt, io};
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     PoolError(PoolError),
+    PoolTimeout(PoolError),
     DieselError(DieselError),
+    #[cfg(feature = "runtime-tokio")]
+    TaskJoin(tokio::task::JoinError),
+    SchemaMismatch {
+        table: String,
+        error: DieselError,
+    },
+    DuplicateRule {
+        ptype: String,
+        rule: Vec<String>,
+    },
+    RuleNotFound {
+        ptype: String,
+        rule: Vec<String>,
+    },
+    RuleTooLong {
+        ptype: String,
+        rule: Vec<String>,
+    },
+    InvalidFieldIndex {
+        ptype: String,
+        field_index: usize,
+    },
+    BlankPtype {
+        rule: Vec<String>,
+    },
+    UnknownPtype {
+        ptype: String,
+        rule: Vec<String>,
+    },
+    EmptyRule {
+        ptype: String,
+    },
+    FieldCountMismatch {
+        ptype: String,
+        rule: Vec<String>,
+        expected: usize,
+    },
+    SectionMismatch {
+        sec: String,
+        ptype: String,
+        expected: String,
+    },
+    ValueTooLong {
+        ptype: String,
+        rule: Vec<String>,
+        column: String,
+        limit: usize,
+    },
+    RuleOutsideFilter {
+        ptype: String,
+        rule: Vec<String>,
+        field_index: usize,
+        field_values: Vec<String>,
+    },
+    RuleCountMismatch {
+        old: usize,
+        new: usize,
+    },
+    VersionConflict {
+        expected: i64,
+        actual: i64,
+    },
+    SnapshotExists(String),
+    SnapshotNotFound(String),
+    ChangesPruned {
+        pruned: i64,
+    },
+    NoDatabaseUrl,
+    Io(io::Error),
+    InvalidCsv {
+        line: usize,
+        reason: String,
+    },
+    CopyMismatch {
+        expected_rules: usize,
+        actual_rules: usize,
+        expected_checksum: u64,
+        actual_checksum: u64,
+    },
+    #[cfg(feature = "serde")]
+    InvalidDocument {
+        ptype: String,
+        index: usize,
+        reason: String,
+    },
+    #[cfg(feature = "serde")]
+    Json(serde_json::Error),
+    #[cfg(feature = "serde")]
+    Yaml(serde_yaml::Error),
 }
 
 impl fmt::Display for Error {
@@ -14,7 +105,127 @@ impl fmt::Display for Error {
 
         match self {
             PoolError(pool_err) => pool_err.fmt(f),
+            PoolTimeout(pool_err) => write!(
+                f,
+                "timed out waiting for a database connection: {}",
+                pool_err
+            ),
             DieselError(diesel_error) => diesel_error.fmt(f),
+            #[cfg(feature = "runtime-tokio")]
+            TaskJoin(join_err) => write!(f, "blocking database task failed: {}", join_err),
+            SchemaMismatch { table, error } => write!(
+                f,
+                "table {} does not have the expected columns: {}",
+                table, error
+            ),
+            DuplicateRule { ptype, rule } => {
+                write!(f, "rule {} {:?} already exists", ptype, rule)
+            }
+            RuleNotFound { ptype, rule } => write!(f, "rule {} {:?} does not exist", ptype, rule),
+            RuleTooLong { ptype, rule } => write!(
+                f,
+                "rule {} {:?} has {} values, at most 6 can be stored",
+                ptype,
+                rule,
+                rule.len()
+            ),
+            InvalidFieldIndex { ptype, field_index } => write!(
+                f,
+                "field index {} is out of range for ptype {}, the last field is 5",
+                field_index, ptype
+            ),
+            BlankPtype { rule } => write!(f, "rule {:?} has a blank ptype", rule),
+            UnknownPtype { ptype, rule } => write!(
+                f,
+                "rule {} {:?} has a ptype the model does not define",
+                ptype, rule
+            ),
+            EmptyRule { ptype } => write!(f, "rule {} has no values", ptype),
+            FieldCountMismatch {
+                ptype,
+                rule,
+                expected,
+            } => write!(
+                f,
+                "rule {} {:?} has {} values, the model defines {}",
+                ptype,
+                rule,
+                rule.len(),
+                expected
+            ),
+            SectionMismatch {
+                sec,
+                ptype,
+                expected,
+            } => write!(
+                f,
+                "ptype {} is defined in section {}, not {}",
+                ptype, expected, sec
+            ),
+            ValueTooLong {
+                ptype,
+                rule,
+                column,
+                limit,
+            } => write!(
+                f,
+                "rule {} {:?} does not fit column {}, which holds at most {} characters",
+                ptype, rule, column, limit
+            ),
+            RuleOutsideFilter {
+                ptype,
+                rule,
+                field_index,
+                field_values,
+            } => write!(
+                f,
+                "rule {} {:?} does not match the filter {:?} at field index {}",
+                ptype, rule, field_values, field_index
+            ),
+            RuleCountMismatch { old, new } => write!(
+                f,
+                "expected as many new rules as old rules, got {} old and {} new",
+                old, new
+            ),
+            VersionConflict { expected, actual } => write!(
+                f,
+                "policy version conflict: expected {}, found {}",
+                expected, actual
+            ),
+            SnapshotExists(name) => write!(f, "snapshot {:?} already exists", name),
+            SnapshotNotFound(name) => write!(f, "snapshot {:?} not found", name),
+            NoDatabaseUrl => write!(
+                f,
+                "the adapter was created from a pool and has no database URL to connect to"
+            ),
+            ChangesPruned { pruned } => write!(
+                f,
+                "changes up to {} have been pruned from the change log",
+                pruned
+            ),
+            Io(io_err) => io_err.fmt(f),
+            InvalidCsv { line, reason } => write!(f, "invalid CSV on line {}: {}", line, reason),
+            CopyMismatch {
+                expected_rules,
+                actual_rules,
+                expected_checksum,
+                actual_checksum,
+            } => write!(
+                f,
+                "destination does not hold the source rules: expected {} rules with checksum \
+                 {:016x}, found {} with checksum {:016x}",
+                expected_rules, expected_checksum, actual_rules, actual_checksum
+            ),
+            #[cfg(feature = "serde")]
+            InvalidDocument {
+                ptype,
+                index,
+                reason,
+            } => write!(f, "invalid {:?} rule at index {}: {}", ptype, index, reason),
+            #[cfg(feature = "serde")]
+            Json(json_err) => json_err.fmt(f),
+            #[cfg(feature = "serde")]
+            Yaml(yaml_err) => yaml_err.fmt(f),
         }
     }
 }
@@ -24,8 +235,40 @@ impl StdError for Error {
         use Error::*;
 
         match self {
-            PoolError(pool_err) => Some(pool_err),
-            DieselError(diesel_error) => Some(diesel_error),
+            PoolError(pool_err) | PoolTimeout(pool_err) => Some(pool_err),
+            DieselError(diesel_error)
+            | SchemaMismatch {
+                error: diesel_error,
+                ..
+            } => Some(diesel_error),
+            #[cfg(feature = "runtime-tokio")]
+            TaskJoin(join_err) => Some(join_err),
+            Io(io_err) => Some(io_err),
+            #[cfg(feature = "serde")]
+            Json(json_err) => Some(json_err),
+            #[cfg(feature = "serde")]
+            Yaml(yaml_err) => Some(yaml_err),
+            DuplicateRule { .. }
+            | RuleNotFound { .. }
+            | RuleTooLong { .. }
+            | InvalidFieldIndex { .. }
+            | BlankPtype { .. }
+            | UnknownPtype { .. }
+            | EmptyRule { .. }
+            | FieldCountMismatch { .. }
+            | SectionMismatch { .. }
+            | ValueTooLong { .. }
+            | RuleOutsideFilter { .. }
+            | RuleCountMismatch { .. }
+            | VersionConflict { .. }
+            | SnapshotExists(_)
+            | SnapshotNotFound(_)
+            | ChangesPruned { .. }
+            | NoDatabaseUrl
+            | InvalidCsv { .. }
+            | CopyMismatch { .. } => None,
+            #[cfg(feature = "serde")]
+            InvalidDocument { .. } => None,
         }
     }
 }
@@ -41,3 +284,23 @@ impl From<DieselError> for Error {
         Error::DieselError(err)
     }
 }
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Json(err)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<serde_yaml::Error> for Error {
+    fn from(err: serde_yaml::Error) -> Self {
+        Error::Yaml(err)
+    }
+}
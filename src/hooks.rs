@@ -0,0 +1,85 @@
+use casbin::Result;
+
+use crate::changes::{ImportMode, PolicyChange};
+
+/// A write requested through `DieselAdapter`, as passed to a `PolicyHook`.
+///
+/// Rules are given as the caller passed them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyMutation {
+    /// `add_policy`, `add_policies` and their `ignore_duplicates` variants.
+    AddPolicies {
+        ptype: String,
+        rules: Vec<Vec<String>>,
+    },
+    /// `remove_policy`, `remove_policies` and `remove_policies_with_mode`.
+    RemovePolicies {
+        ptype: String,
+        rules: Vec<Vec<String>>,
+    },
+    /// `remove_filtered_policy` and `remove_filtered_policy_returning`.
+    RemoveFilteredPolicy {
+        ptype: String,
+        field_index: usize,
+        field_values: Vec<String>,
+    },
+    /// `update_policy` and `update_policies`.
+    UpdatePolicies {
+        ptype: String,
+        old_rules: Vec<Vec<String>>,
+        new_rules: Vec<Vec<String>>,
+    },
+    UpdateFilteredPolicies {
+        ptype: String,
+        field_index: usize,
+        field_values: Vec<String>,
+        new_rules: Vec<Vec<String>>,
+    },
+    ReplaceFilteredPolicy {
+        ptype: String,
+        field_index: usize,
+        field_values: Vec<String>,
+        new_rules: Vec<Vec<String>>,
+    },
+    ClearPolicy,
+    /// `restore_policy_at`, with the timestamp restored to in milliseconds
+    /// since the Unix epoch.
+    RestorePolicy {
+        timestamp: i64,
+    },
+    /// `import_csv`, `import_json`, `import_yaml` or `import_document`, with
+    /// the import mode. The rules are not known until they have been read.
+    Import {
+        mode: ImportMode,
+    },
+    /// `copy_policy_from`, on the adapter copied into. The rules are not known
+    /// until they have been read.
+    CopyPolicy,
+    /// `restore_snapshot`, with the name of the snapshot restored.
+    RestoreSnapshot {
+        name: String,
+    },
+    /// `save_policy`, `save_policy_if_version` and `save_policy_diff`, with
+    /// every rule of the model as `(ptype, rule)`.
+    SavePolicy {
+        rules: Vec<(String, Vec<String>)>,
+    },
+}
+
+/// Callbacks run around every write made through `DieselAdapter`.
+///
+/// Hooks run in registration order on the task that awaits the write, outside
+/// the blocking pool the write itself runs on. They run on the async runtime,
+/// so they should not block for long. `before` runs before the write takes a
+/// pooled connection.
+pub trait PolicyHook: Send + Sync {
+    /// Runs before the write starts. Returning an error cancels the write and
+    /// hands the error back to the caller; later hooks are not run.
+    fn before(&self, _mutation: &PolicyMutation) -> Result<()> {
+        Ok(())
+    }
+
+    /// Runs once the write has committed, with the rules it actually added and
+    /// removed. `changes` is empty when the write changed nothing.
+    fn after(&self, _mutation: &PolicyMutation, _changes: &[PolicyChange]) {}
+}
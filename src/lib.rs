@@ -2,14 +2,41 @@
 extern crate diesel;
 
 mod adapter;
+mod audit;
+mod changes;
+mod copy;
+mod csv;
+#[cfg(feature = "serde")]
+mod document;
 mod error;
+mod hooks;
+mod snapshot;
+mod validation;
 
 mod models;
+mod query;
 mod schema;
 
 mod actions;
 
+mod watcher;
+
 pub use casbin;
 
 pub use adapter::DieselAdapter;
+pub use audit::{AuditEntry, AuditQuery};
+pub use changes::{
+    BatchRemoval, ChangeKind, ImportMode, PolicyChange, PolicyChanges, PolicyDiff, PolicyUpdate,
+    RemovalMode, SaveSummary,
+};
+pub use copy::CopyReport;
+#[cfg(feature = "serde")]
+pub use document::PolicyDocument;
 pub use error::Error;
+pub use hooks::{PolicyHook, PolicyMutation};
+pub use query::RuleQuery;
+pub use snapshot::{Snapshot, SnapshotDiff};
+
+#[cfg(feature = "postgres")]
+pub use watcher::PgWatcher;
+pub use watcher::PollingWatcher;
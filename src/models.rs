@@ -1,4 +1,4 @@
-use super::schema::casbin_rule;
+use super::schema::{casbin_audit_log, casbin_rule, casbin_rule_change, casbin_snapshot};
 
 #[derive(Queryable, Identifiable)]
 #[diesel(table_name = casbin_rule)]
@@ -13,7 +13,7 @@ pub(crate) struct CasbinRule {
     pub v5: String,
 }
 
-#[derive(Insertable, Clone)]
+#[derive(Queryable, Insertable, AsChangeset, Clone, PartialEq, Eq, Hash)]
 #[diesel(table_name = casbin_rule)]
 pub(crate) struct NewCasbinRule {
     pub ptype: String,
@@ -24,3 +24,96 @@ pub(crate) struct NewCasbinRule {
     pub v4: String,
     pub v5: String,
 }
+
+impl From<CasbinRule> for NewCasbinRule {
+    fn from(rule: CasbinRule) -> Self {
+        NewCasbinRule {
+            ptype: rule.ptype,
+            v0: rule.v0,
+            v1: rule.v1,
+            v2: rule.v2,
+            v3: rule.v3,
+            v4: rule.v4,
+            v5: rule.v5,
+        }
+    }
+}
+
+impl From<CasbinRuleChange> for NewCasbinRule {
+    fn from(change: CasbinRuleChange) -> Self {
+        NewCasbinRule {
+            ptype: change.ptype,
+            v0: change.v0,
+            v1: change.v1,
+            v2: change.v2,
+            v3: change.v3,
+            v4: change.v4,
+            v5: change.v5,
+        }
+    }
+}
+
+#[derive(Queryable, Identifiable)]
+#[diesel(table_name = casbin_rule_change)]
+pub(crate) struct CasbinRuleChange {
+    pub id: i64,
+    pub op: String,
+    pub ptype: String,
+    pub v0: String,
+    pub v1: String,
+    pub v2: String,
+    pub v3: String,
+    pub v4: String,
+    pub v5: String,
+    pub created_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = casbin_rule_change)]
+pub(crate) struct NewCasbinRuleChange<'a> {
+    pub op: &'a str,
+    pub ptype: &'a str,
+    pub v0: &'a str,
+    pub v1: &'a str,
+    pub v2: &'a str,
+    pub v3: &'a str,
+    pub v4: &'a str,
+    pub v5: &'a str,
+    pub created_at: i64,
+}
+
+#[derive(Queryable, Identifiable)]
+#[diesel(table_name = casbin_audit_log)]
+pub(crate) struct CasbinAuditLog {
+    pub id: i64,
+    pub operation: String,
+    pub op: String,
+    pub ptype: String,
+    pub v0: String,
+    pub v1: String,
+    pub v2: String,
+    pub v3: String,
+    pub v4: String,
+    pub v5: String,
+    pub actor: String,
+    pub created_at: i64,
+}
+
+#[derive(Queryable, Identifiable)]
+#[diesel(table_name = casbin_snapshot)]
+pub(crate) struct CasbinSnapshot {
+    pub id: i32,
+    pub name: String,
+    pub version: i64,
+    pub rule_count: i64,
+    pub created_at: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = casbin_snapshot)]
+pub(crate) struct NewCasbinSnapshot<'a> {
+    pub name: &'a str,
+    pub version: i64,
+    pub rule_count: i64,
+    pub created_at: i64,
+}
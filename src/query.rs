@@ -0,0 +1,11 @@
+/// Filters for reading rules with `DieselAdapter::for_each_rule`. Every field
+/// left as `None` matches all rules.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuleQuery {
+    pub ptype: Option<String>,
+    /// Only rules holding this value, in the field at `field_index` if set,
+    /// or in any field.
+    pub value: Option<String>,
+    /// Ignored unless `value` is set.
+    pub field_index: Option<usize>,
+}
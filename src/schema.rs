@@ -10,3 +10,73 @@ table! {
         v5 -> Varchar,
     }
 }
+
+table! {
+    casbin_rule_change (id) {
+        id -> BigInt,
+        op -> Varchar,
+        ptype -> Varchar,
+        v0 -> Varchar,
+        v1 -> Varchar,
+        v2 -> Varchar,
+        v3 -> Varchar,
+        v4 -> Varchar,
+        v5 -> Varchar,
+        created_at -> BigInt,
+    }
+}
+
+table! {
+    casbin_policy_version (id) {
+        id -> Integer,
+        version -> BigInt,
+    }
+}
+
+table! {
+    casbin_rule_change_pruned (id) {
+        id -> Integer,
+        pruned_through -> BigInt,
+    }
+}
+
+table! {
+    casbin_audit_log (id) {
+        id -> BigInt,
+        operation -> Varchar,
+        op -> Varchar,
+        ptype -> Varchar,
+        v0 -> Varchar,
+        v1 -> Varchar,
+        v2 -> Varchar,
+        v3 -> Varchar,
+        v4 -> Varchar,
+        v5 -> Varchar,
+        actor -> Varchar,
+        created_at -> BigInt,
+    }
+}
+
+table! {
+    casbin_snapshot (id) {
+        id -> Integer,
+        name -> Varchar,
+        version -> BigInt,
+        rule_count -> BigInt,
+        created_at -> BigInt,
+    }
+}
+
+table! {
+    casbin_snapshot_rule (id) {
+        id -> Integer,
+        snapshot_id -> Integer,
+        ptype -> Varchar,
+        v0 -> Varchar,
+        v1 -> Varchar,
+        v2 -> Varchar,
+        v3 -> Varchar,
+        v4 -> Varchar,
+        v5 -> Varchar,
+    }
+}
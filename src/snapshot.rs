@@ -0,0 +1,25 @@
+/// A named copy of every rule, taken by `create_snapshot`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub name: String,
+    /// The policy version the snapshot was taken at.
+    pub version: i64,
+    pub rule_count: usize,
+    /// Milliseconds since the Unix epoch.
+    pub created_at: i64,
+}
+
+/// Rules that differ between two snapshots, as `(ptype, rule)` pairs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    /// Rules in the second snapshot but not the first.
+    pub added: Vec<(String, Vec<String>)>,
+    /// Rules in the first snapshot but not the second.
+    pub removed: Vec<(String, Vec<String>)>,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
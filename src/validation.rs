@@ -0,0 +1,154 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{adapter::rule_line, csv::MAX_VALUES, error::Error, models::NewCasbinRule};
+
+// The widths the rule table's columns are declared with, as `(ptype, value)`
+// lengths in characters. Postgres columns are unbounded.
+#[cfg(feature = "postgres")]
+const COLUMN_LIMITS: Option<(usize, usize)> = None;
+#[cfg(any(feature = "mysql", feature = "sqlite"))]
+const COLUMN_LIMITS: Option<(usize, usize)> = Some((12, 128));
+
+const VALUE_COLUMNS: [&str; MAX_VALUES] = ["v0", "v1", "v2", "v3", "v4", "v5"];
+
+// What a model defines for one of its policy ptypes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PtypeDefinition {
+    pub(crate) sec: String,
+    // The number of values each rule holds, e.g. 3 for `p = sub, obj, act`.
+    pub(crate) fields: usize,
+}
+
+/// The checks strict mode runs on rules and filters before they are written.
+#[derive(Clone)]
+pub(crate) struct Validator {
+    // The policy ptypes of the model rules are checked against, if any.
+    ptypes: Option<Arc<HashMap<String, PtypeDefinition>>>,
+}
+
+impl Validator {
+    pub(crate) fn new(ptypes: Option<Arc<HashMap<String, PtypeDefinition>>>) -> Self {
+        Self { ptypes }
+    }
+
+    pub(crate) fn rule(&self, ptype: &str, rule: &[String]) -> Result<(), Error> {
+        self.stored_rule(ptype, rule)?;
+
+        match self.definition(ptype) {
+            Some(def) if def.fields != rule.len() => Err(Error::FieldCountMismatch {
+                ptype: ptype.to_owned(),
+                rule: rule.to_vec(),
+                expected: def.fields,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // Checks everything but the field count, which a rule read back from a
+    // row cannot be held to: trailing empty values are not told apart from
+    // missing ones there.
+    fn stored_rule(&self, ptype: &str, rule: &[String]) -> Result<(), Error> {
+        self.ptype(ptype, rule)?;
+
+        if rule.is_empty() {
+            return Err(Error::EmptyRule {
+                ptype: ptype.to_owned(),
+            });
+        }
+        if rule.len() > MAX_VALUES {
+            return Err(Error::RuleTooLong {
+                ptype: ptype.to_owned(),
+                rule: rule.to_vec(),
+            });
+        }
+
+        if let Some((ptype_limit, value_limit)) = COLUMN_LIMITS {
+            let too_long = |column: &str, limit: usize| Error::ValueTooLong {
+                ptype: ptype.to_owned(),
+                rule: rule.to_vec(),
+                column: column.to_owned(),
+                limit,
+            };
+
+            if ptype.chars().count() > ptype_limit {
+                return Err(too_long("ptype", ptype_limit));
+            }
+            for (value, column) in rule.iter().zip(VALUE_COLUMNS.iter()) {
+                if value.chars().count() > value_limit {
+                    return Err(too_long(column, value_limit));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn rules(&self, ptype: &str, rules: &[Vec<String>]) -> Result<(), Error> {
+        rules.iter().try_for_each(|rule| self.rule(ptype, rule))
+    }
+
+    pub(crate) fn new_rule(&self, rule: &NewCasbinRule) -> Result<(), Error> {
+        match rule_line(rule) {
+            Some((ptype, values)) => {
+                self.stored_rule(&ptype, &values)?;
+
+                match self.definition(&ptype) {
+                    Some(def) if values.len() > def.fields => Err(Error::FieldCountMismatch {
+                        ptype,
+                        rule: values,
+                        expected: def.fields,
+                    }),
+                    _ => Ok(()),
+                }
+            }
+            None => Err(Error::EmptyRule {
+                ptype: rule.ptype.clone(),
+            }),
+        }
+    }
+
+    // A filter must name at least one value, and its last value must fall on
+    // one of the six value columns.
+    pub(crate) fn filter(
+        &self,
+        ptype: &str,
+        field_index: usize,
+        field_values: &[String],
+    ) -> Result<(), Error> {
+        self.ptype(ptype, field_values)?;
+
+        if field_values.is_empty() {
+            return Err(Error::EmptyRule {
+                ptype: ptype.to_owned(),
+            });
+        }
+        if field_index + field_values.len() > MAX_VALUES {
+            return Err(Error::InvalidFieldIndex {
+                ptype: ptype.to_owned(),
+                field_index,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn ptype(&self, ptype: &str, rule: &[String]) -> Result<(), Error> {
+        if ptype.trim().is_empty() {
+            return Err(Error::BlankPtype {
+                rule: rule.to_vec(),
+            });
+        }
+
+        match self.ptypes {
+            Some(ref ptypes) if !ptypes.contains_key(ptype) => Err(Error::UnknownPtype {
+                ptype: ptype.to_owned(),
+                rule: rule.to_vec(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn definition(&self, ptype: &str) -> Option<&PtypeDefinition> {
+        self.ptypes.as_ref().and_then(|ptypes| ptypes.get(ptype))
+    }
+}
@@ -0,0 +1,299 @@
+use casbin::{error::AdapterError, EventData, Result, Watcher};
+use diesel::r2d2::{ConnectionManager, Pool};
+
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{actions as adapter, changes::PolicyUpdate, error::*};
+
+type Callback = Box<dyn FnMut(String) + Send + Sync>;
+
+#[cfg(feature = "postgres")]
+pub const DEFAULT_NOTIFY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Watcher that listens for the notifications `DieselAdapter` sends on
+/// Postgres whenever a write changes the policy.
+///
+/// The update callback receives a `PolicyUpdate` in its string form for every
+/// committed write, including writes made through this process. Parse it with
+/// `PolicyUpdate::parse` and pass its checkpoint to `load_incremental_policy`,
+/// or simply reload the policy.
+///
+/// `update` does nothing: the adapter already notifies from inside its write
+/// transactions, so there is nothing left to publish once the enforcer calls it.
+#[cfg(feature = "postgres")]
+pub struct PgWatcher {
+    worker: Worker,
+}
+
+#[cfg(feature = "postgres")]
+impl PgWatcher {
+    /// Starts listening on a dedicated connection to `url`, held in a pool of
+    /// one so it is reopened if it breaks.
+    pub fn new<U: Into<String>>(url: U) -> Result<Self> {
+        Self::with_poll_interval(url, DEFAULT_NOTIFY_POLL_INTERVAL)
+    }
+
+    /// Like `new`, but checks for notifications every `interval` instead of
+    /// every `DEFAULT_NOTIFY_POLL_INTERVAL`.
+    pub fn with_poll_interval<U: Into<String>>(url: U, interval: Duration) -> Result<Self> {
+        let pool = Pool::builder()
+            .connection_timeout(Duration::from_secs(10))
+            .max_size(1)
+            .build(ConnectionManager::new(url))
+            .map_err(|err| AdapterError(Box::new(Error::PoolError(err))))?;
+
+        Self::with_pool(pool, interval)
+    }
+
+    /// Starts listening on a connection taken from `pool`, which the watcher
+    /// keeps until it is dropped, so do not pass a pool other callers rely on.
+    ///
+    /// The connection stops listening before it goes back to the pool, so
+    /// other queries never run on a connection that collects notifications.
+    pub fn with_pool(
+        pool: Pool<ConnectionManager<adapter::Connection>>,
+        interval: Duration,
+    ) -> Result<Self> {
+        let conn = pool
+            .get()
+            .map_err(|err| AdapterError(Box::new(Error::PoolTimeout(err))))?;
+        let mut conn = Subscription::new(conn)?;
+        let version = adapter::policy_version(&mut conn.0)?;
+
+        let worker = Worker::spawn(interval, move |worker| {
+            let mut listener = Listener {
+                pool,
+                conn: Some(conn),
+                version,
+            };
+            move || listener.poll(&worker)
+        });
+
+        Ok(Self { worker })
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl Watcher for PgWatcher {
+    fn set_update_callback(&mut self, cb: Callback) {
+        self.worker.set_callback(cb);
+    }
+
+    fn update(&mut self, _d: EventData) {}
+}
+
+/// Watcher that polls the policy version and reports every change to it.
+///
+/// Works on every backend. The update callback receives a `PolicyUpdate` in
+/// its string form, covering every write committed since the previous poll,
+/// including writes made through this process. Parse it with
+/// `PolicyUpdate::parse` and pass its checkpoint to `load_incremental_policy`,
+/// or simply reload the policy.
+///
+/// `update` does nothing: every write already lands in the change log, which
+/// is all the other watchers need to see.
+pub struct PollingWatcher {
+    worker: Worker,
+}
+
+impl PollingWatcher {
+    /// Starts polling every `DEFAULT_POLL_INTERVAL`, borrowing a connection
+    /// from `pool` for each poll.
+    pub fn new(pool: Pool<ConnectionManager<adapter::Connection>>) -> Result<Self> {
+        Self::with_poll_interval(pool, DEFAULT_POLL_INTERVAL)
+    }
+
+    /// Like `new`, but polls every `interval`.
+    pub fn with_poll_interval(
+        pool: Pool<ConnectionManager<adapter::Connection>>,
+        interval: Duration,
+    ) -> Result<Self> {
+        let mut version = current_version(&pool)?;
+
+        let worker = Worker::spawn(interval, move |worker| {
+            move || {
+                if let Ok(latest) = current_version(&pool) {
+                    if latest > version {
+                        worker.notify(
+                            PolicyUpdate {
+                                checkpoint: version,
+                                version: latest,
+                            }
+                            .to_string(),
+                        );
+                        version = latest;
+                    }
+                }
+            }
+        });
+
+        Ok(Self { worker })
+    }
+}
+
+impl Watcher for PollingWatcher {
+    fn set_update_callback(&mut self, cb: Callback) {
+        self.worker.set_callback(cb);
+    }
+
+    fn update(&mut self, _d: EventData) {}
+}
+
+fn current_version(pool: &Pool<ConnectionManager<adapter::Connection>>) -> Result<i64> {
+    let mut conn = pool
+        .get()
+        .map_err(|err| AdapterError(Box::new(Error::PoolTimeout(err))))?;
+
+    adapter::policy_version(&mut conn)
+}
+
+#[derive(Clone, Default)]
+struct Shared {
+    callback: Arc<Mutex<Option<Callback>>>,
+    // Set once the worker is dropped; the condvar wakes the thread early.
+    stop: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl Shared {
+    fn notify(&self, payload: String) {
+        if let Some(cb) = self.callback.lock().unwrap().as_mut() {
+            cb(payload);
+        }
+    }
+}
+
+// Runs a poll function on a background thread every interval until dropped.
+// Dropping it wakes the thread instead of waiting out the interval.
+struct Worker {
+    shared: Shared,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    fn spawn<F, P>(interval: Duration, f: F) -> Self
+    where
+        F: FnOnce(Shared) -> P + Send + 'static,
+        P: FnMut(),
+    {
+        let shared = Shared::default();
+        let worker = shared.clone();
+
+        let handle = thread::spawn(move || {
+            let stop = Arc::clone(&worker.stop);
+            let (stopped, wake) = &*stop;
+            let mut poll = f(worker);
+
+            while !*stopped.lock().unwrap() {
+                poll();
+
+                let guard = stopped.lock().unwrap();
+                let _ = wake.wait_timeout_while(guard, interval, |stopped| !*stopped);
+            }
+        });
+
+        Self {
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    fn set_callback(&mut self, cb: Callback) {
+        *self.shared.callback.lock().unwrap() = Some(cb);
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let (stopped, wake) = &*self.shared.stop;
+        *stopped.lock().unwrap() = true;
+        wake.notify_all();
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(feature = "postgres")]
+struct Listener {
+    pool: Pool<ConnectionManager<adapter::Connection>>,
+    conn: Option<Subscription>,
+    // Latest version passed to the callback.
+    version: i64,
+}
+
+#[cfg(feature = "postgres")]
+impl Listener {
+    fn poll(&mut self, worker: &Shared) {
+        let conn = match self.conn.take() {
+            Some(conn) => Ok(conn),
+            None => self.reconnect(worker),
+        };
+
+        if let Ok(mut conn) = conn {
+            if let Ok(payloads) = adapter::notifications(&mut conn.0) {
+                for payload in payloads {
+                    if let Some(update) = PolicyUpdate::parse(&payload) {
+                        self.version = self.version.max(update.version);
+                    }
+                    worker.notify(payload);
+                }
+                self.conn = Some(conn);
+            }
+        }
+    }
+
+    // Notifications sent while the connection was down are lost, so anything
+    // committed in the meantime is reported as a single update.
+    fn reconnect(&mut self, worker: &Shared) -> Result<Subscription> {
+        let conn = self
+            .pool
+            .get()
+            .map_err(|err| AdapterError(Box::new(Error::PoolTimeout(err))))?;
+        let mut conn = Subscription::new(conn)?;
+
+        let version = adapter::policy_version(&mut conn.0)?;
+        if version > self.version {
+            worker.notify(
+                PolicyUpdate {
+                    checkpoint: self.version,
+                    version,
+                }
+                .to_string(),
+            );
+            self.version = version;
+        }
+
+        Ok(conn)
+    }
+}
+
+// A pooled connection listening on the notify channel. It stops listening,
+// and drops any notifications it has not handed out, before it goes back to
+// the pool.
+#[cfg(feature = "postgres")]
+struct Subscription(adapter::Pool);
+
+#[cfg(feature = "postgres")]
+impl Subscription {
+    fn new(mut conn: adapter::Pool) -> Result<Self> {
+        adapter::listen(&mut conn)?;
+
+        Ok(Self(conn))
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if adapter::unlisten(&mut self.0).is_ok() {
+            let _ = adapter::notifications(&mut self.0);
+        }
+    }
+}
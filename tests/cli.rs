@@ -0,0 +1,209 @@
+use serde_json::{json, Value};
+
+use std::{
+    env, fs,
+    io::Write,
+    path::PathBuf,
+    process::{self, Command, Stdio},
+};
+
+// A SQLite database of its own, removed once the test is done.
+struct Database(PathBuf);
+
+impl Database {
+    fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("casbin-diesel-{}-{}.db", name, process::id()));
+        let _ = fs::remove_file(&path);
+
+        Self(path)
+    }
+
+    fn url(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+
+    fn run(&self, args: &[&str]) -> String {
+        self.run_with_input(args, "")
+    }
+
+    fn run_with_input(&self, args: &[&str], input: &str) -> String {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_casbin-diesel"))
+            .arg("--database-url")
+            .arg(&self.0)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+
+        let output = child.wait_with_output().unwrap();
+        assert!(
+            output.status.success(),
+            "{:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    fn json(&self, args: &[&str]) -> Value {
+        let mut json_args = vec!["--json"];
+        json_args.extend_from_slice(args);
+
+        serde_json::from_str(&self.run(&json_args)).unwrap()
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[test]
+fn test_add_list_remove() {
+    let db = Database::new("add-list-remove");
+
+    assert_eq!(
+        db.json(&["add", "p", "alice", "data1", "read"]),
+        json!({ "ptype": "p", "rule": ["alice", "data1", "read"], "added": true })
+    );
+    assert_eq!(
+        db.json(&["add", "p", "alice", "data1", "read"])["added"],
+        json!(false)
+    );
+    db.json(&["add", "p", "bob", "data2", "write"]);
+    db.json(&["add", "g", "alice", "admin"]);
+
+    assert_eq!(
+        db.json(&["list"]),
+        json!({
+            "g": [["alice", "admin"]],
+            "p": [["alice", "data1", "read"], ["bob", "data2", "write"]],
+        })
+    );
+    assert_eq!(
+        db.json(&["list", "--ptype", "g"]),
+        json!({ "g": [["alice", "admin"]] })
+    );
+    assert_eq!(
+        db.json(&["search", "alice"]),
+        json!({ "g": [["alice", "admin"]], "p": [["alice", "data1", "read"]] })
+    );
+    assert_eq!(
+        db.json(&["search", "data2", "--field-index", "1"]),
+        json!({ "p": [["bob", "data2", "write"]] })
+    );
+    assert_eq!(
+        db.json(&["search", "data2", "--field-index", "0"]),
+        json!({})
+    );
+
+    assert_eq!(
+        db.json(&["remove", "p", "alice", "data1", "read"]),
+        json!({ "ptype": "p", "rule": ["alice", "data1", "read"], "removed": true })
+    );
+    assert_eq!(
+        db.json(&["remove", "p", "alice", "data1", "read"])["removed"],
+        json!(false)
+    );
+    assert_eq!(db.run(&["list"]), "p, bob, data2, write\ng, alice, admin\n");
+}
+
+#[test]
+fn test_remove_filtered() {
+    let db = Database::new("remove-filtered");
+
+    db.json(&["add", "p", "alice", "data1", "read"]);
+    db.json(&["add", "p", "alice", "data2", "write"]);
+    db.json(&["add", "p", "bob", "data1", "write"]);
+
+    assert_eq!(
+        db.json(&["remove-filtered", "p", "1", "data1"]),
+        json!({
+            "ptype": "p",
+            "removed": [["alice", "data1", "read"], ["bob", "data1", "write"]],
+        })
+    );
+    // an empty value matches anything
+    assert_eq!(
+        db.run(&["remove-filtered", "p", "0", "", "data3"]),
+        "0 rules removed\n"
+    );
+    assert_eq!(db.run(&["list"]), "p, alice, data2, write\n");
+}
+
+#[test]
+fn test_import_export() {
+    let db = Database::new("import-export");
+
+    let document = json!({
+        "p": [["alice", "data1", "read"], ["bob", "data2", "write"]],
+        "g": [["alice", "admin"]],
+    });
+    assert_eq!(
+        db.run_with_input(
+            &["--json", "import", "-", "--format", "json"],
+            &document.to_string()
+        ),
+        format!("{:#}\n", json!({ "inserted": 3, "deleted": 0 }))
+    );
+
+    let csv = db.run(&["export"]);
+    assert_eq!(
+        csv,
+        "g, alice, admin\np, alice, data1, read\np, bob, data2, write\n"
+    );
+    assert_eq!(
+        serde_json::from_str::<Value>(&db.run(&["export", "--format", "json"])).unwrap(),
+        document
+    );
+
+    // a CSV export imported elsewhere holds the same rules
+    let other = Database::new("import-export-other");
+    other.json(&["add", "p", "carol", "data3", "read"]);
+    assert_eq!(
+        other.run_with_input(&["import", "-", "--replace"], &csv),
+        "3 rules inserted, 1 deleted\n"
+    );
+    assert_eq!(other.run(&["export"]), csv);
+}
+
+#[test]
+fn test_copy() {
+    let source = Database::new("copy-source");
+    let dest = Database::new("copy-dest");
+
+    source.json(&["add", "p", "alice", "data1", "read"]);
+    source.json(&["add", "p", "bob", "data2", "write"]);
+    source.json(&["add", "g", "alice", "admin"]);
+    dest.json(&["add", "p", "bob", "data2", "write"]);
+
+    let report = source.json(&["copy", "--to", dest.url(), "--batch-size", "2"]);
+    assert_eq!(
+        (&report["read"], &report["copied"], &report["duplicates"]),
+        (&json!(3), &json!(2), &json!(1))
+    );
+    assert_eq!(
+        dest.json(&["list"]),
+        json!({
+            "g": [["alice", "admin"]],
+            "p": [["bob", "data2", "write"], ["alice", "data1", "read"]],
+        })
+    );
+
+    let again = source.json(&["copy", "--to", dest.url()]);
+    assert_eq!(
+        (&again["copied"], &again["duplicates"]),
+        (&json!(0), &json!(3))
+    );
+    assert_eq!(again["checksum"], report["checksum"]);
+}